@@ -0,0 +1,126 @@
+// Throughput of the replay cache and of timestamp issuance under concurrent load
+use authentic_time_service::nonce::{MemoryNonceStore, NonceStore};
+use authentic_time_service::{BatchConfig, TimeAuthority, TimeAuthorityImpl, TimestampRequest};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Concurrent tasks issuing requests in each iteration
+const TASKS: u64 = 8;
+
+/// Requests made by each task per iteration
+const REQUESTS_PER_TASK: u64 = 1_000;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn bench_nonce_store(c: &mut Criterion) {
+    let runtime = runtime();
+    let store = Arc::new(MemoryNonceStore::new());
+    let counter = Arc::new(AtomicU64::new(0));
+    let expiry = Duration::from_secs(300);
+
+    let mut group = c.benchmark_group("nonce_store");
+    group.throughput(Throughput::Elements(TASKS * REQUESTS_PER_TASK));
+    group.bench_function("check_and_insert", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let tasks = (0..TASKS).map(|_| {
+                let store = store.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    for _ in 0..REQUESTS_PER_TASK {
+                        let nonce = format!("{:x}", counter.fetch_add(1, Ordering::Relaxed));
+                        let fresh = store
+                            .check_and_insert(&nonce, SystemTime::now(), expiry)
+                            .await
+                            .unwrap();
+                        assert!(fresh);
+                    }
+                })
+            });
+            for task in futures::future::join_all(tasks).await {
+                task.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_issue_timestamp(c: &mut Criterion) {
+    let runtime = runtime();
+    let authority = Arc::new(TimeAuthorityImpl::new("bench.authority".to_string()));
+    let counter = Arc::new(AtomicU64::new(0));
+
+    let mut group = c.benchmark_group("authority");
+    group.throughput(Throughput::Elements(TASKS * REQUESTS_PER_TASK));
+    group.sample_size(20);
+    group.bench_function("issue_timestamp", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let tasks = (0..TASKS).map(|_| {
+                let authority = authority.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    for _ in 0..REQUESTS_PER_TASK {
+                        let nonce = format!("{:x}", counter.fetch_add(1, Ordering::Relaxed));
+                        let response = authority
+                            .issue_timestamp(TimestampRequest::new(nonce))
+                            .await
+                            .unwrap();
+                        assert!(response.is_success());
+                    }
+                })
+            });
+            for task in futures::future::join_all(tasks).await {
+                task.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_issue_batched(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut authority = TimeAuthorityImpl::new("bench.authority".to_string());
+    authority.set_batching(Some(BatchConfig::new(Duration::from_millis(1), 1024)));
+    let authority = Arc::new(authority);
+    let counter = Arc::new(AtomicU64::new(0));
+
+    let mut group = c.benchmark_group("authority");
+    group.throughput(Throughput::Elements(TASKS * REQUESTS_PER_TASK));
+    group.sample_size(20);
+    group.bench_function("issue_timestamp_batched", |b| {
+        b.to_async(&runtime).iter(|| async {
+            // each task keeps all of its requests in flight so batches fill up
+            let tasks = (0..TASKS).map(|_| {
+                let authority = authority.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let requests = (0..REQUESTS_PER_TASK).map(|_| {
+                        let nonce = format!("{:x}", counter.fetch_add(1, Ordering::Relaxed));
+                        authority.issue_timestamp(TimestampRequest::new(nonce))
+                    });
+                    for response in futures::future::join_all(requests).await {
+                        assert!(response.unwrap().is_success());
+                    }
+                })
+            });
+            for task in futures::future::join_all(tasks).await {
+                task.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_nonce_store,
+    bench_issue_timestamp,
+    bench_issue_batched
+);
+criterion_main!(benches);
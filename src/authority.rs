@@ -1,15 +1,37 @@
 // Implementation of the Time Authority
 
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Timelike, Utc};
 use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
 use rand::rngs::OsRng;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::batch::{self, BatchConfig, Batcher, Queued};
+use crate::chain::{ConsistencyRequest, InclusionRequest, LogRange, TimestampLog};
+use crate::clock::{ClockSource, SystemClock};
 use crate::error::TimeServiceError;
-use crate::models::{AuthenticTimestamp, TimestampRequest, TimestampResponse, TimestampStatus};
+use crate::health::ClockHealth;
+use crate::imprint::HashAlgorithm;
+use crate::keys::{self, KeyFormat};
+use crate::merkle::{ConsistencyProof, InclusionProof, MerkleLog};
+use crate::models::{
+    Accuracy, AuthenticTimestamp, AuthorityMetadata, KeyValidity, SignedTreeHead, TimestampRequest,
+    TimestampResponse, TimestampStatus, CURRENT_PAYLOAD_VERSION,
+};
+use crate::nonce::{MemoryNonceStore, NonceStore};
+use crate::ratelimit::{RateLimitConfig, RateLimiter, Requester};
+use crate::roughtime;
+use crate::serial::SerialCounter;
+
+/// Retry hint sent while the clock is unsynchronized, in seconds
+const CLOCK_RETRY_AFTER_SECS: u64 = 5;
+
+/// How often expired nonces are purged from the nonce store
+const NONCE_PURGE_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Interface for time authority
 #[async_trait]
@@ -38,47 +60,305 @@ pub struct TimeAuthorityImpl {
     /// keypair used for signing timestamps
     keypair: Keypair,
 
-    /// cache of recently issued timestamps to prevent replay
-    recent_requests: Arc<Mutex<HashMap<String, SystemTime>>>,
+    /// identifier and validity window of the current signing key
+    current_key: KeyValidity,
+
+    /// keys retired by rotation, kept so their timestamps remain verifiable
+    retired_keys: Vec<KeyValidity>,
+
+    /// nonces of recently served requests, to prevent replay
+    nonce_store: Arc<dyn NonceStore>,
 
     /// time after which a nonce expires from the cache
     nonce_expiry: Duration,
 
+    /// unix time (ms) after which the next request purges expired nonces
+    next_purge_millis: AtomicU64,
+
     /// optional list of trusted client IDs
     trusted_clients: Option<HashMap<String, PublicKey>>,
+
+    /// token buckets limiting how often timestamps are issued
+    rate_limiter: RateLimiter,
+
+    /// clock read when issuing timestamps and expiring nonces
+    clock: Arc<dyn ClockSource>,
+
+    /// synchronization state of `clock`
+    clock_health: Arc<ClockHealth>,
+
+    /// largest clock error bound at which timestamps are still issued (None never refuses)
+    max_clock_error: Option<Duration>,
+
+    /// accuracy the clock is known to keep regardless of synchronization (e.g. a GPS clock)
+    declared_accuracy: Option<Accuracy>,
+
+    /// fractional-second digits issued times are truncated to (None keeps full resolution)
+    precision: Option<u8>,
+
+    /// serial numbers and the latest issue time
+    serials: SerialCounter,
+
+    /// hash-chained log of issued timestamps
+    log: TimestampLog,
+
+    /// which replica of the authority this is, if it is replicated
+    replica_id: Option<String>,
+
+    /// collects requests to sign together (None signs each request on its own)
+    batcher: Option<Batcher>,
 }
 
 impl TimeAuthorityImpl {
     /// create a new time authority with the given identifier
     pub fn new(id: String) -> Self {
+        Self::with_clock(id, Arc::new(SystemClock))
+    }
+
+    /// create a new time authority reading time from the given clock
+    ///
+    /// The fresh key's validity starts at the clock's current time, so the
+    /// clock is set before the key is created.
+    pub fn with_clock(id: String, clock: Arc<dyn ClockSource>) -> Self {
         let mut csprng = OsRng {};
         let keypair = Keypair::generate(&mut csprng);
 
-        Self {
-            id,
-            keypair,
-            recent_requests: Arc::new(Mutex::new(HashMap::new())),
-            nonce_expiry: Duration::from_secs(300), // 5 minutes for example
-            trusted_clients: None,
-        }
+        // a freshly generated key cannot have signed anything earlier
+        let mut authority = Self::with_keypair(id, keypair);
+        authority.set_clock(clock);
+        authority.current_key.valid_from = authority.clock.now();
+        authority
     }
 
     /// create a new time authority with an existing keypair
+    ///
+    /// The key's validity start is unknown, so it is treated as valid from the
+    /// beginning of time; use `set_key_valid_from` to narrow it.
     pub fn with_keypair(id: String, keypair: Keypair) -> Self {
+        let current_key = KeyValidity {
+            key_id: keys::key_id(&keypair.public),
+            public_key: keypair.public.to_bytes().to_vec(),
+            valid_from: DateTime::<Utc>::MIN_UTC,
+            valid_until: None,
+        };
+
         Self {
             id,
             keypair,
-            recent_requests: Arc::new(Mutex::new(HashMap::new())),
+            current_key,
+            retired_keys: Vec::new(),
+            nonce_store: Arc::new(MemoryNonceStore::new()),
             nonce_expiry: Duration::from_secs(300), // 5 minutes
+            next_purge_millis: AtomicU64::new(0),
             trusted_clients: None,
+            rate_limiter: RateLimiter::default(),
+            clock: Arc::new(SystemClock),
+            clock_health: Arc::new(ClockHealth::default()),
+            max_clock_error: None,
+            declared_accuracy: None,
+            precision: None,
+            serials: SerialCounter::new(),
+            log: TimestampLog::default(),
+            replica_id: None,
+            batcher: None,
         }
     }
 
+    /// load a time authority whose signing key is stored in a key file
+    pub fn from_key_file<P: AsRef<Path>>(
+        id: String,
+        path: P,
+        format: KeyFormat,
+        passphrase: Option<&str>,
+    ) -> Result<Self, TimeServiceError> {
+        let keypair = keys::load_keypair(path, format, passphrase)?;
+        Ok(Self::with_keypair(id, keypair))
+    }
+
+    /// save this authority's signing key to a key file
+    pub fn save_key_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: KeyFormat,
+        passphrase: Option<&str>,
+    ) -> Result<(), TimeServiceError> {
+        keys::save_keypair(path, &self.keypair, format, passphrase)
+    }
+
+    /// set the instant from which the current key may sign timestamps
+    pub fn set_key_valid_from(&mut self, valid_from: DateTime<Utc>) {
+        self.current_key.valid_from = valid_from;
+    }
+
+    /// rotate to a new signing key, retiring the current one.
+    ///
+    /// The retired key stays valid for `overlap` after the rotation so that
+    /// timestamps issued by replicas that have not rotated yet still verify.
+    pub fn rotate_key(&mut self, keypair: Keypair, overlap: Duration) -> KeyValidity {
+        let now = self.clock.now();
+        let overlap =
+            chrono::Duration::from_std(overlap).unwrap_or_else(|_| chrono::Duration::zero());
+
+        let mut retired = std::mem::replace(
+            &mut self.current_key,
+            KeyValidity {
+                key_id: keys::key_id(&keypair.public),
+                public_key: keypair.public.to_bytes().to_vec(),
+                valid_from: now,
+                valid_until: None,
+            },
+        );
+        retired.valid_until = Some(now + overlap);
+        self.retired_keys.push(retired.clone());
+        self.keypair = keypair;
+
+        retired
+    }
+
+    /// restore a previously retired key, e.g. after a restart
+    pub fn add_retired_key(&mut self, key: KeyValidity) {
+        self.retired_keys.push(key);
+    }
+
+    /// the current key followed by all retired keys
+    pub fn key_history(&self) -> Vec<KeyValidity> {
+        let mut history = vec![self.current_key.clone()];
+        history.extend(self.retired_keys.iter().cloned());
+        history
+    }
+
     /// Set the nonce expiry duration
     pub fn set_nonce_expiry(&mut self, expiry: Duration) {
         self.nonce_expiry = expiry;
     }
 
+    /// replace the nonce store, e.g. with a durable or shared one
+    ///
+    /// Replicas given the same store reject each other's replays.
+    pub fn set_nonce_store(&mut self, store: Arc<dyn NonceStore>) {
+        self.nonce_store = store;
+    }
+
+    /// read time from the given clock instead of the system clock
+    pub fn set_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        self.clock = clock;
+    }
+
+    /// synchronization state of the authority's clock, for recording measurements
+    pub fn clock_health(&self) -> Arc<ClockHealth> {
+        self.clock_health.clone()
+    }
+
+    /// replace the clock health tracker, e.g. with one shared by replicas on the same host
+    pub fn set_clock_health(&mut self, health: Arc<ClockHealth>) {
+        self.clock_health = health;
+    }
+
+    /// refuse to issue timestamps while the clock's error bound exceeds `limit`
+    /// (or the clock was never synchronized); None signs regardless
+    pub fn set_max_clock_error(&mut self, limit: Option<Duration>) {
+        self.max_clock_error = limit;
+    }
+
+    /// state an accuracy for timestamps even when the clock error bound is
+    /// smaller or unknown
+    pub fn set_declared_accuracy(&mut self, accuracy: Option<Accuracy>) {
+        self.declared_accuracy = accuracy;
+    }
+
+    /// truncate issued times to `digits` fractional-second digits (at most 9)
+    pub fn set_precision(&mut self, digits: Option<u8>) {
+        self.precision = digits.map(|digits| digits.min(9));
+    }
+
+    /// continue serial numbers from the given counter, e.g. one persisted across restarts
+    pub fn set_serial_counter(&mut self, serials: SerialCounter) {
+        self.serials = serials;
+    }
+
+    /// name this replica of the authority
+    ///
+    /// Each replica keeps its own serials and log, so replicas sharing an
+    /// authority id need distinct replica ids for their timestamps and tree
+    /// heads not to look like equivocation.
+    pub fn set_replica_id(&mut self, replica_id: Option<String>) {
+        self.replica_id = replica_id;
+    }
+
+    /// continue the timestamp log from the given one, e.g. one persisted across restarts
+    pub fn set_log(&mut self, log: TimestampLog) {
+        self.log = log;
+    }
+
+    /// sign requests in batches, one signature per batch (None signs each request)
+    pub fn set_batching(&mut self, config: Option<BatchConfig>) {
+        self.batcher = config.map(Batcher::new);
+    }
+
+    /// issued timestamps with serials in `range`, in issuance order
+    ///
+    /// A batch is a single entry, represented by its first request.
+    pub fn log_range(&self, range: LogRange) -> Result<Vec<AuthenticTimestamp>, TimeServiceError> {
+        self.log.range(range)
+    }
+
+    /// hash of the most recently issued timestamp
+    pub fn log_head(&self) -> Option<Vec<u8>> {
+        self.log.head()
+    }
+
+    /// sign the current size and root of the transparency log
+    pub fn signed_tree_head(&self) -> Result<SignedTreeHead, TimeServiceError> {
+        let (tree_size, root_hash) = self.log.tree_head()?;
+        let mut head = SignedTreeHead {
+            authority_id: self.id.clone(),
+            replica_id: self.replica_id.clone(),
+            tree_size,
+            root_hash,
+            timestamp: self.clock.now(),
+            key_id: Some(self.current_key.key_id.clone()),
+            signature: vec![],
+        };
+        head.signature = self
+            .keypair
+            .sign(&head.signing_payload())
+            .to_bytes()
+            .to_vec();
+        Ok(head)
+    }
+
+    /// prove that a timestamp is in the transparency log at a given tree size
+    pub fn inclusion_proof(
+        &self,
+        request: &InclusionRequest,
+    ) -> Result<InclusionProof, TimeServiceError> {
+        self.log.inclusion_proof(request)
+    }
+
+    /// prove that one tree head of the transparency log extends another
+    pub fn consistency_proof(
+        &self,
+        request: ConsistencyRequest,
+    ) -> Result<ConsistencyProof, TimeServiceError> {
+        self.log.consistency_proof(request)
+    }
+
+    /// set the rate limits applied to timestamp requests
+    pub fn set_rate_limits(&mut self, config: RateLimitConfig) {
+        self.rate_limiter = RateLimiter::new(config);
+    }
+
+    /// describe this authority for clients
+    pub fn metadata(&self) -> AuthorityMetadata {
+        AuthorityMetadata {
+            authority_id: self.id.clone(),
+            public_key: self.get_public_key(),
+            keys: self.key_history(),
+            payload_version: CURRENT_PAYLOAD_VERSION,
+            hash_algorithms: vec![HashAlgorithm::Sha256, HashAlgorithm::Sha512],
+        }
+    }
+
     /// add trusted client to this authority
     pub fn add_trusted_client(&mut self, client_id: String, client_pubkey: PublicKey) {
         if self.trusted_clients.is_none() {
@@ -90,16 +370,341 @@ impl TimeAuthorityImpl {
         }
     }
 
-    /// clean expired nonces from the cache
-    fn clean_expired_nonces(&self) {
-        let now = SystemTime::now();
-        let mut cache = self.recent_requests.lock().unwrap();
+    /// sign the canonical payload of a timestamp with this authority's key
+    fn sign_timestamp(&self, timestamp: &mut AuthenticTimestamp) -> Result<(), TimeServiceError> {
+        let payload = timestamp.signing_payload()?;
+        timestamp.signature = self.keypair.sign(&payload).to_bytes().to_vec();
+        Ok(())
+    }
 
-        cache.retain(|_, &mut timestamp| {
-            now.duration_since(timestamp)
-                .unwrap_or(Duration::from_secs(0))
-                < self.nonce_expiry
-        });
+    /// build a signed refusal that echoes the request's nonce and imprint
+    fn refuse(
+        &self,
+        request: TimestampRequest,
+        status: TimestampStatus,
+        retry_after_secs: Option<u64>,
+    ) -> Result<TimestampResponse, TimeServiceError> {
+        let mut response = self.refusal(request, status, retry_after_secs);
+        let payload = response.signing_payload()?;
+        response.timestamp.signature = self.keypair.sign(&payload).to_bytes().to_vec();
+        Ok(response)
+    }
+
+    /// an unsigned refusal of the request with the given status
+    fn refusal(
+        &self,
+        request: TimestampRequest,
+        status: TimestampStatus,
+        retry_after_secs: Option<u64>,
+    ) -> TimestampResponse {
+        TimestampResponse {
+            timestamp: AuthenticTimestamp {
+                timestamp: self.clock.now(),
+                nonce: request.nonce,
+                authority_id: self.id.clone(),
+                message_imprint: request.message_imprint,
+                key_id: Some(self.current_key.key_id.clone()),
+                replica_id: None,
+                serial: None,
+                previous_hash: None,
+                batch: None,
+                version: CURRENT_PAYLOAD_VERSION,
+                accuracy: None,
+                precision: None,
+                signature: vec![],
+            },
+            status,
+            retry_after_secs,
+        }
+    }
+
+    /// issue a timestamp for a request received from the given network source
+    ///
+    /// The source is only used to rate limit anonymous clients.
+    pub async fn issue_timestamp_from(
+        &self,
+        request: TimestampRequest,
+        source: Option<&str>,
+    ) -> Result<TimestampResponse, TimeServiceError> {
+        // Clean expired nonces, at most once per purge interval
+        let now = SystemTime::from(self.clock.now());
+        let now_millis = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u64;
+        let due = self.next_purge_millis.load(Ordering::Relaxed);
+        if now_millis >= due
+            && self
+                .next_purge_millis
+                .compare_exchange(
+                    due,
+                    now_millis + NONCE_PURGE_INTERVAL.as_millis() as u64,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            self.nonce_store
+                .purge_expired(now, self.nonce_expiry)
+                .await?;
+        }
+
+        // Charge the request to the client (or its source, until the client is
+        // authenticated) and the authority as a whole before signing anything,
+        // so refusals can't be used to make the authority sign at will
+        let authorized = self.is_client_authorized(&request);
+        if let Err(wait) = self.rate_limiter.check(
+            &self.requester(&request, authorized, source),
+            Instant::now(),
+        ) {
+            // round up so a client that waits as told finds a token
+            let retry_after = wait.map(|wait| {
+                wait.as_secs()
+                    .saturating_add(u64::from(wait.subsec_nanos() > 0))
+            });
+            return Ok(self.refusal(request, TimestampStatus::RateLimited, retry_after));
+        }
+
+        // If client authorization is enabled, check if client is authorized
+        if !authorized {
+            return self.refuse(request, TimestampStatus::Unauthorized, None);
+        }
+
+        // Only the current payload format is issued; checked before the nonce is
+        // spent, so a corrected retry can reuse it
+        if request
+            .payload_version
+            .is_some_and(|version| version != CURRENT_PAYLOAD_VERSION)
+        {
+            return self.refuse(request, TimestampStatus::UnsupportedVersion, None);
+        }
+
+        // Reject imprints whose digest doesn't fit the declared algorithm
+        if request
+            .message_imprint
+            .as_ref()
+            .is_some_and(|imprint| !imprint.is_well_formed())
+        {
+            return self.refuse(request, TimestampStatus::MalformedRequest, None);
+        }
+
+        // Refuse to sign with a clock that isn't known to be accurate enough
+        if !self.clock_is_trusted(self.clock.now()) {
+            return self.refuse(
+                request,
+                TimestampStatus::ClockUnsynchronized,
+                Some(CLOCK_RETRY_AFTER_SECS),
+            );
+        }
+
+        // Check for replay attacks, remembering the nonce if it is fresh
+        if !self
+            .nonce_store
+            .check_and_insert(&request.nonce, now, self.nonce_expiry)
+            .await?
+        {
+            return self.refuse(request, TimestampStatus::NonceReused, None);
+        }
+
+        // Sign alone, or wait for the batch this request joins
+        let authentic_timestamp = match &self.batcher {
+            Some(batcher) => self.issue_batched(batcher, request).await?,
+            None => self.issue_single(request).await?,
+        };
+
+        Ok(TimestampResponse {
+            timestamp: authentic_timestamp,
+            status: TimestampStatus::Success,
+            retry_after_secs: None,
+        })
+    }
+
+    /// sign a timestamp for a single request
+    async fn issue_single(
+        &self,
+        request: TimestampRequest,
+    ) -> Result<AuthenticTimestamp, TimeServiceError> {
+        // Link to the previous entry of the log; appends take turns, keeping serials in chain order
+        self.log
+            .append(|previous_hash| async move {
+                let mut authentic_timestamp = AuthenticTimestamp {
+                    nonce: request.nonce,
+                    message_imprint: request.message_imprint,
+                    ..self.stamp(previous_hash).await?
+                };
+
+                // Sign the canonical payload
+                self.sign_timestamp(&mut authentic_timestamp)?;
+                Ok(authentic_timestamp)
+            })
+            .await
+    }
+
+    /// add a request to the open batch and wait until the batch is signed
+    async fn issue_batched(
+        &self,
+        batcher: &Batcher,
+        request: TimestampRequest,
+    ) -> Result<AuthenticTimestamp, TimeServiceError> {
+        let mut ticket = batcher.enqueue(request);
+        let reply = match ticket.full {
+            Some(batch) => {
+                self.sign_batch(batch).await;
+                ticket.receiver.await
+            }
+            None => match tokio::time::timeout_at(ticket.deadline, &mut ticket.receiver).await {
+                Ok(reply) => reply,
+                // nobody signed the batch by its deadline, so this request does
+                Err(_) => {
+                    self.sign_batch(batcher.take(ticket.batch_id)).await;
+                    ticket.receiver.await
+                }
+            },
+        };
+
+        reply
+            .map_err(|_| TimeServiceError::generic("batch was dropped before it was signed"))?
+            .map_err(TimeServiceError::Generic)
+    }
+
+    /// sign a batch with one signature over the Merkle root of its requests
+    /// and deliver each request its timestamp and inclusion proof
+    async fn sign_batch(&self, batch: Vec<Queued>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let mut signed = Vec::with_capacity(batch.len());
+        let (requests, members) = (&batch, &mut signed);
+        // the whole batch is a single entry of the log, under a single serial
+        let appended = self
+            .log
+            .append(|previous_hash| async move {
+                let template = self.stamp(previous_hash).await?;
+                *members = requests
+                    .iter()
+                    .map(|queued| AuthenticTimestamp {
+                        nonce: queued.request.nonce.clone(),
+                        message_imprint: queued.request.message_imprint.clone(),
+                        ..template.clone()
+                    })
+                    .collect();
+
+                let tree =
+                    MerkleLog::from_leaves(members.iter().map(|m| m.batch_leaf_hash()).collect());
+                for (index, member) in members.iter_mut().enumerate() {
+                    member.batch = Some(tree.inclusion_proof(index as u64, tree.len())?);
+                }
+
+                // the signature covers the root, so every member carries the same one
+                self.sign_timestamp(&mut members[0])?;
+                let signature = members[0].signature.clone();
+                for member in &mut members[1..] {
+                    member.signature = signature.clone();
+                }
+                Ok(members[0].clone())
+            })
+            .await;
+
+        match appended {
+            Ok(_) => {
+                for (queued, member) in batch.into_iter().zip(signed) {
+                    let _ = queued.reply.send(Ok(member));
+                }
+            }
+            Err(e) => batch::fail(batch, &e),
+        }
+    }
+
+    /// the issuance fields of the next timestamp: time, accuracy and serial
+    ///
+    /// The nonce and imprint are left empty for the caller to fill in.
+    async fn stamp(
+        &self,
+        previous_hash: Option<Vec<u8>>,
+    ) -> Result<AuthenticTimestamp, TimeServiceError> {
+        // Create timestamp at the configured precision, stating how far off it may be
+        let clock_time = self.clock.now();
+        let truncated = match self.precision {
+            Some(precision) => truncate_to_precision(clock_time, precision),
+            None => clock_time,
+        };
+
+        // Never issue a later serial at an earlier time, even if the clock steps back
+        let (serial, issued_at) = self.serials.next(truncated).await?;
+        let clock_error = self.clock_error(clock_time);
+        // the time stated differs from the clock by truncation, or by holding it after a step back
+        let adjustment = (clock_time - issued_at).abs().to_std().unwrap_or_default();
+
+        Ok(AuthenticTimestamp {
+            timestamp: issued_at,
+            nonce: String::new(),
+            authority_id: self.id.clone(),
+            message_imprint: None,
+            key_id: Some(self.current_key.key_id.clone()),
+            replica_id: self.replica_id.clone(),
+            serial: Some(serial),
+            previous_hash,
+            batch: None,
+            version: CURRENT_PAYLOAD_VERSION,
+            accuracy: clock_error.map(|error| Accuracy::from_duration(error + adjustment)),
+            precision: self.precision,
+            signature: vec![],
+        })
+    }
+
+    /// whether the clock is accurate enough to sign with at `now`
+    fn clock_is_trusted(&self, now: DateTime<Utc>) -> bool {
+        match self.max_clock_error {
+            Some(limit) => self
+                .clock_health
+                .error_bound(now)
+                .is_some_and(|bound| bound <= limit),
+            None => true,
+        }
+    }
+
+    /// how far the clock may be off at `clock_time`, if known
+    fn clock_error(&self, clock_time: DateTime<Utc>) -> Option<Duration> {
+        [
+            self.declared_accuracy
+                .map(|accuracy| accuracy.as_duration()),
+            self.clock_health.error_bound(clock_time),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+
+    /// read the clock for protocols that sign time directly (e.g. Roughtime)
+    ///
+    /// Returns the time and how far it may be off, or None while the clock
+    /// isn't accurate enough to sign with.
+    pub fn clock_reading(&self) -> Option<(DateTime<Utc>, Option<Duration>)> {
+        let now = self.clock.now();
+        self.clock_is_trusted(now)
+            .then(|| (now, self.clock_error(now)))
+    }
+
+    /// sign a Roughtime delegation (DELE) of an online key with the long-term key
+    pub fn sign_roughtime_delegation(&self, delegation: &[u8]) -> Vec<u8> {
+        let mut message = roughtime::DELEGATION_CONTEXT.to_vec();
+        message.extend_from_slice(delegation);
+        self.keypair.sign(&message).to_bytes().to_vec()
+    }
+
+    /// who a request is charged to for rate limiting
+    fn requester(
+        &self,
+        request: &TimestampRequest,
+        authorized: bool,
+        source: Option<&str>,
+    ) -> Requester {
+        // client ids only identify a client when the authority verifies them
+        match (&self.trusted_clients, &request.client_id) {
+            (Some(_), Some(client_id)) if authorized => Requester::Authenticated(client_id.clone()),
+            _ => Requester::Anonymous(source.map(str::to_string)),
+        }
     }
 
     /// Check if client is authorized - if authorization is enabled
@@ -130,6 +735,14 @@ impl TimeAuthorityImpl {
     }
 }
 
+/// drop the fractional-second digits beyond `precision`
+fn truncate_to_precision(time: DateTime<Utc>, precision: u8) -> DateTime<Utc> {
+    let unit = 10u32.pow(9 - u32::from(precision.min(9)));
+    let nanos = time.timestamp_subsec_nanos();
+    // leap-second nanos (>= 1e9) are truncated within the leap second as well
+    time.with_nanosecond(nanos / unit * unit).unwrap_or(time)
+}
+
 #[async_trait]
 impl TimeAuthority for TimeAuthorityImpl {
     fn get_id(&self) -> String {
@@ -140,62 +753,7 @@ impl TimeAuthority for TimeAuthorityImpl {
         &self,
         request: TimestampRequest,
     ) -> Result<TimestampResponse, TimeServiceError> {
-        // Clean expired nonces
-        self.clean_expired_nonces();
-
-        // Check for replay attacks
-        {
-            let mut cache = self.recent_requests.lock().unwrap();
-            if cache.contains_key(&request.nonce) {
-                return Ok(TimestampResponse {
-                    timestamp: AuthenticTimestamp {
-                        timestamp: Utc::now(),
-                        nonce: request.nonce,
-                        authority_id: self.id.clone(),
-                        signature: vec![],
-                    },
-                    status: TimestampStatus::RateLimitExceeded,
-                });
-            }
-
-            // Add nonce to cache
-            cache.insert(request.nonce.clone(), SystemTime::now());
-        }
-
-        // If client authorization is enabled, check if client is authorized
-        if !self.is_client_authorized(&request) {
-            return Ok(TimestampResponse {
-                timestamp: AuthenticTimestamp {
-                    timestamp: Utc::now(),
-                    nonce: request.nonce,
-                    authority_id: self.id.clone(),
-                    signature: vec![],
-                },
-                status: TimestampStatus::AuthenticationFailed,
-            });
-        }
-
-        // Create timestamp
-        let timestamp = Utc::now();
-
-        // Create message to sign (timestamp + nonce)
-        let message = format!("{}{}", timestamp.to_rfc3339(), request.nonce);
-
-        // Sign message
-        let signature = self.keypair.sign(message.as_bytes());
-
-        // Create response
-        let authentic_timestamp = AuthenticTimestamp {
-            timestamp,
-            nonce: request.nonce,
-            authority_id: self.id.clone(),
-            signature: signature.to_bytes().to_vec(),
-        };
-
-        Ok(TimestampResponse {
-            timestamp: authentic_timestamp,
-            status: TimestampStatus::Success,
-        })
+        self.issue_timestamp_from(request, None).await
     }
 
     fn verify_timestamp(&self, timestamp: &AuthenticTimestamp) -> bool {
@@ -204,8 +762,11 @@ impl TimeAuthority for TimeAuthorityImpl {
             return false;
         }
 
-        // Create message that was signed
-        let message = timestamp.format_message();
+        // Create message that was signed (legacy or canonical, per version)
+        let message = match timestamp.signing_payload() {
+            Ok(m) => m,
+            Err(_) => return false,
+        };
 
         // Create signature object
         let signature = match Signature::from_bytes(&timestamp.signature) {
@@ -213,11 +774,19 @@ impl TimeAuthority for TimeAuthorityImpl {
             Err(_) => return false,
         };
 
-        // Verify signature
-        self.keypair
-            .public
-            .verify(message.as_bytes(), &signature)
-            .is_ok()
+        // Verify against the named key (or any key, for timestamps without a key id)
+        // whose validity window covers the timestamp
+        self.key_history()
+            .iter()
+            .filter(|key| {
+                timestamp
+                    .key_id
+                    .as_ref()
+                    .is_none_or(|key_id| *key_id == key.key_id)
+            })
+            .filter(|key| key.covers(timestamp.timestamp))
+            .filter_map(|key| PublicKey::from_bytes(&key.public_key).ok())
+            .any(|pubkey| pubkey.verify(&message, &signature).is_ok())
     }
 
     fn get_public_key(&self) -> Vec<u8> {
@@ -252,6 +821,398 @@ mod tests {
 
         // Second request with same nonce should fail
         let response2 = authority.issue_timestamp(request).await.unwrap();
-        assert_eq!(response2.status, TimestampStatus::RateLimitExceeded);
+        assert_eq!(response2.status, TimestampStatus::NonceReused);
+    }
+
+    #[tokio::test]
+    async fn test_nonce_expiry_follows_the_clock() {
+        use crate::clock::ManualClock;
+        use chrono::TimeZone;
+
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_clock(clock.clone());
+        authority.set_nonce_expiry(Duration::from_secs(60));
+
+        let request = TimestampRequest::new("test-nonce-clock".to_string());
+        let response = authority.issue_timestamp(request.clone()).await.unwrap();
+        assert_eq!(response.timestamp.timestamp, start);
+
+        clock.advance(chrono::Duration::seconds(59));
+        let replayed = authority.issue_timestamp(request.clone()).await.unwrap();
+        assert_eq!(replayed.status, TimestampStatus::NonceReused);
+
+        // once the nonce has expired it is accepted again
+        clock.advance(chrono::Duration::seconds(2));
+        let response = authority.issue_timestamp(request).await.unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+        assert_eq!(
+            response.timestamp.timestamp,
+            start + chrono::Duration::seconds(61)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accuracy_and_precision_are_signed() {
+        use crate::clock::ManualClock;
+        use crate::policy::{Verdict, VerificationPolicy};
+        use chrono::TimeZone;
+
+        let clock_time = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_clock(Arc::new(ManualClock::new(clock_time)));
+        authority.set_key_valid_from(clock_time - chrono::Duration::days(1));
+        authority.set_precision(Some(3));
+        authority.set_declared_accuracy(Some(Accuracy::from_micros(2_000)));
+
+        let request = TimestampRequest::new("test-nonce-accuracy".to_string());
+        let timestamp = authority.issue_timestamp(request).await.unwrap().timestamp;
+
+        // truncated to milliseconds, with the dropped digits added to the accuracy
+        assert_eq!(
+            timestamp.timestamp,
+            Utc.timestamp_opt(1_700_000_000, 123_000_000).unwrap()
+        );
+        assert_eq!(timestamp.precision, Some(3));
+        assert_eq!(
+            timestamp.accuracy,
+            Some(Accuracy {
+                seconds: 0,
+                millis: 2,
+                micros: 457
+            })
+        );
+        let (earliest, latest) = timestamp.interval().unwrap();
+        assert!(earliest <= clock_time && clock_time <= latest);
+        assert!(authority.verify_timestamp(&timestamp));
+
+        let mut overstated = timestamp.clone();
+        overstated.accuracy = Some(Accuracy::from_micros(1));
+        assert!(!authority.verify_timestamp(&overstated));
+
+        // out-of-range fields are rejected, whether parsed or built in code
+        let mut out_of_range = timestamp.clone();
+        out_of_range.accuracy = Some(Accuracy {
+            seconds: 0,
+            millis: 1_002,
+            micros: 457,
+        });
+        assert!(!authority.verify_timestamp(&out_of_range));
+        let mut json = serde_json::to_value(&timestamp).unwrap();
+        json["accuracy"]["micros"] = serde_json::json!(1_000);
+        assert!(serde_json::from_value::<AuthenticTimestamp>(json).is_err());
+
+        // an accuracy reaching past the representable range is clamped
+        let mut boundless = timestamp.clone();
+        boundless.accuracy = Some(Accuracy::from_micros(u64::MAX));
+        assert_eq!(
+            boundless.interval(),
+            Some((DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC))
+        );
+
+        // relying parties can insist on a better accuracy
+        let mut client = crate::client::TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+        client.set_policy(VerificationPolicy::new().with_max_accuracy(Duration::from_millis(1)));
+        assert_eq!(
+            client.evaluate_timestamp_at(&timestamp, clock_time),
+            Verdict::Inaccurate {
+                accuracy: Some(Duration::from_micros(2_457))
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_times_never_decrease_in_serial_order() {
+        use crate::clock::ManualClock;
+        use chrono::TimeZone;
+
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_clock(clock.clone());
+
+        let mut issued = Vec::new();
+        for (i, step_ms) in [0, 0, 250, -2_000, 100, 5_000].iter().enumerate() {
+            clock.advance(chrono::Duration::milliseconds(*step_ms));
+            let request = TimestampRequest::new(format!("test-nonce-serial-{}", i));
+            issued.push(authority.issue_timestamp(request).await.unwrap().timestamp);
+        }
+
+        for pair in issued.windows(2) {
+            assert!(pair[1].serial > pair[0].serial);
+            assert!(pair[1].timestamp >= pair[0].timestamp);
+        }
+        // after the clock stepped back 2 s the previous time was held
+        assert_eq!(issued[3].timestamp, issued[2].timestamp);
+        assert_eq!(issued[0].serial, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_replicas_sharing_a_nonce_store_reject_replays() {
+        let store: Arc<dyn NonceStore> = Arc::new(MemoryNonceStore::new());
+        let mut replica_a = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut replica_b = TimeAuthorityImpl::new("test.authority".to_string());
+        replica_a.set_nonce_store(store.clone());
+        replica_b.set_nonce_store(store);
+
+        let request = TimestampRequest::new("test-nonce-shared".to_string());
+        let response = replica_a.issue_timestamp(request.clone()).await.unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+
+        let replayed = replica_b.issue_timestamp(request).await.unwrap();
+        assert_eq!(replayed.status, TimestampStatus::NonceReused);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_requests_get_retry_hint() {
+        use crate::ratelimit::RateLimit;
+
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_rate_limits(
+            RateLimitConfig::default().with_anonymous(RateLimit::new(1, 0.1).unwrap()),
+        );
+
+        let first = TimestampRequest::new("rate-1".to_string());
+        let response = authority
+            .issue_timestamp_from(first, Some("192.0.2.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+
+        let second = TimestampRequest::new("rate-2".to_string());
+        let response = authority
+            .issue_timestamp_from(second.clone(), Some("192.0.2.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status, TimestampStatus::RateLimited);
+        assert_eq!(response.retry_after(), Some(Duration::from_secs(10)));
+        assert!(response.timestamp.signature.is_empty());
+
+        // Another source has its own quota, and the refused nonce wasn't consumed
+        let response = authority
+            .issue_timestamp_from(second, Some("192.0.2.2"))
+            .await
+            .unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests_are_charged_before_refusals_are_signed() {
+        use crate::ratelimit::RateLimit;
+
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_rate_limits(
+            RateLimitConfig::default().with_anonymous(RateLimit::new(2, 0.0).unwrap()),
+        );
+
+        let malformed = |nonce: &str| {
+            TimestampRequest::new(nonce.to_string())
+                .with_payload_version(crate::models::PAYLOAD_VERSION_LEGACY)
+        };
+        for nonce in ["invalid-1", "invalid-2"] {
+            let response = authority
+                .issue_timestamp_from(malformed(nonce), Some("192.0.2.1"))
+                .await
+                .unwrap();
+            assert_eq!(response.status, TimestampStatus::UnsupportedVersion);
+            assert!(!response.timestamp.signature.is_empty());
+        }
+
+        // once the source's quota is spent, nothing more is signed for it
+        let response = authority
+            .issue_timestamp_from(malformed("invalid-3"), Some("192.0.2.1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status, TimestampStatus::RateLimited);
+        assert!(response.timestamp.signature.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_refusals_are_signed() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = crate::client::TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        let request = client
+            .create_request_for_data(b"refused")
+            .with_payload_version(crate::models::PAYLOAD_VERSION_LEGACY);
+        let response = authority.issue_timestamp(request.clone()).await.unwrap();
+        assert_eq!(response.status, TimestampStatus::UnsupportedVersion);
+        assert_eq!(response.timestamp.nonce, request.nonce);
+        assert_eq!(response.timestamp.message_imprint, request.message_imprint);
+        assert!(client.verify_response(&response).unwrap());
+
+        // The status is covered by the signature
+        let mut altered = response.clone();
+        altered.status = TimestampStatus::RateLimited;
+        assert!(!client.verify_response(&altered).unwrap());
+
+        // A refusal can't pass for a timestamp
+        assert!(!client.verify_timestamp(&response.timestamp).unwrap());
+
+        // The refused request didn't spend its nonce, so the corrected retry succeeds
+        let mut corrected = request;
+        corrected.payload_version = None;
+        let response = authority.issue_timestamp(corrected).await.unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_batched_requests_share_one_signature() {
+        use crate::batch::BatchConfig;
+
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        authority.set_batching(Some(BatchConfig::new(Duration::from_millis(50), 3)));
+        let mut client = crate::client::TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        // three requests fill the batch; a fourth is signed when its window closes
+        let requests: Vec<_> = (0..4)
+            .map(|i| client.create_request_for_data(&[i]))
+            .collect();
+        let responses = futures::future::join_all(
+            requests
+                .iter()
+                .map(|request| authority.issue_timestamp(request.clone())),
+        )
+        .await;
+        let issued: Vec<_> = responses
+            .into_iter()
+            .map(|response| response.unwrap().timestamp)
+            .collect();
+
+        for (timestamp, request) in issued.iter().zip(&requests) {
+            assert_eq!(timestamp.nonce, request.nonce);
+            assert!(client.verify_timestamp(timestamp).unwrap());
+        }
+        assert_eq!(issued[0].signature, issued[2].signature);
+        assert_eq!(issued[0].serial, issued[2].serial);
+        assert_eq!(issued[3].batch.as_ref().unwrap().tree_size, 1);
+
+        // the proof must place this very request under the signed root
+        let mut moved = issued[0].clone();
+        moved.batch = issued[1].batch.clone();
+        assert!(!client.verify_timestamp(&moved).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_signature_binds_authority_id() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let bytes = keypair.to_bytes();
+        let authority = TimeAuthorityImpl::with_keypair("authority.a".to_string(), keypair);
+        let twin = TimeAuthorityImpl::with_keypair(
+            "authority.b".to_string(),
+            Keypair::from_bytes(&bytes).unwrap(),
+        );
+
+        let request = TimestampRequest::new("test-nonce-binding".to_string());
+        let response = authority.issue_timestamp(request).await.unwrap();
+        assert_eq!(response.timestamp.version, CURRENT_PAYLOAD_VERSION);
+
+        // Relabelling the timestamp for another authority sharing the key must fail
+        let mut relabelled = response.timestamp.clone();
+        relabelled.authority_id = "authority.b".to_string();
+        assert!(!twin.verify_timestamp(&relabelled));
+    }
+
+    #[tokio::test]
+    async fn test_imprint_is_signed() {
+        use crate::imprint::{HashAlgorithm, MessageImprint};
+
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let imprint = MessageImprint::from_data(HashAlgorithm::Sha256, b"contract v1");
+        let request = TimestampRequest::new("test-nonce-imprint".to_string()).with_imprint(imprint);
+
+        let response = authority.issue_timestamp(request).await.unwrap();
+        assert!(response.timestamp.covers_data(b"contract v1"));
+        assert!(authority.verify_timestamp(&response.timestamp));
+
+        // Swapping in the imprint of different data invalidates the signature
+        let mut swapped = response.timestamp;
+        swapped.message_imprint = Some(MessageImprint::from_data(
+            HashAlgorithm::Sha256,
+            b"contract v2",
+        ));
+        assert!(!authority.verify_timestamp(&swapped));
+
+        // Truncated digests are refused
+        let malformed = TimestampRequest::new("test-nonce-malformed".to_string())
+            .with_imprint(MessageImprint::new(HashAlgorithm::Sha256, vec![0u8; 4]));
+        let refused = authority.issue_timestamp(malformed.clone()).await.unwrap();
+        assert_eq!(refused.status, TimestampStatus::MalformedRequest);
+
+        // and can be retried with the same nonce once the digest is fixed
+        let mut fixed = malformed;
+        fixed.message_imprint = Some(MessageImprint::from_data(HashAlgorithm::Sha256, b"v1"));
+        let response = authority.issue_timestamp(fixed).await.unwrap();
+        assert_eq!(response.status, TimestampStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation_keeps_old_timestamps_verifiable() {
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let old_key_id = authority.current_key.key_id.clone();
+
+        let before = authority
+            .issue_timestamp(TimestampRequest::new("before-rotation".to_string()))
+            .await
+            .unwrap()
+            .timestamp;
+        assert_eq!(before.key_id.as_deref(), Some(old_key_id.as_str()));
+
+        let retired =
+            authority.rotate_key(Keypair::generate(&mut OsRng {}), Duration::from_secs(0));
+        assert_eq!(retired.key_id, old_key_id);
+
+        let after = authority
+            .issue_timestamp(TimestampRequest::new("after-rotation".to_string()))
+            .await
+            .unwrap()
+            .timestamp;
+        assert_ne!(after.key_id, before.key_id);
+
+        assert!(authority.verify_timestamp(&before));
+        assert!(authority.verify_timestamp(&after));
+        assert_eq!(authority.metadata().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_verify_legacy_timestamp() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut timestamp = AuthenticTimestamp {
+            timestamp: Utc::now(),
+            nonce: "legacy-nonce".to_string(),
+            authority_id: "test.authority".to_string(),
+            message_imprint: None,
+            key_id: None,
+            replica_id: None,
+            serial: None,
+            previous_hash: None,
+            batch: None,
+            version: crate::models::PAYLOAD_VERSION_LEGACY,
+            accuracy: None,
+            precision: None,
+            signature: vec![],
+        };
+        let message = timestamp.format_message();
+        timestamp.signature = authority
+            .keypair
+            .sign(message.as_bytes())
+            .to_bytes()
+            .to_vec();
+
+        assert!(authority.verify_timestamp(&timestamp));
+
+        // Unknown payload versions are rejected
+        timestamp.version = 99;
+        assert!(!authority.verify_timestamp(&timestamp));
     }
 }
@@ -0,0 +1,156 @@
+// Collection of timestamp requests into batches signed with a single signature
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::error::TimeServiceError;
+use crate::models::{AuthenticTimestamp, TimestampRequest};
+
+/// Batch size used when only a window is configured
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 1024;
+
+/// How requests are grouped into batches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchConfig {
+    /// how long the first request of a batch waits for others to join it
+    pub window: Duration,
+
+    /// batches are signed as soon as they reach this many requests
+    pub max_size: usize,
+}
+
+impl BatchConfig {
+    /// Collect requests for up to `window`, signing early at `max_size`
+    pub fn new(window: Duration, max_size: usize) -> Self {
+        Self {
+            window,
+            max_size: max_size.max(1),
+        }
+    }
+}
+
+/// Outcome delivered to a request waiting in a batch
+pub type BatchReply = Result<AuthenticTimestamp, String>;
+
+/// A request waiting for its batch to be signed
+pub struct Queued {
+    /// the accepted request
+    pub request: TimestampRequest,
+
+    /// where to deliver its timestamp
+    pub reply: oneshot::Sender<BatchReply>,
+}
+
+/// A request's place in the open batch
+pub struct Ticket {
+    /// where its timestamp will arrive
+    pub receiver: oneshot::Receiver<BatchReply>,
+
+    /// the batch it joined
+    pub batch_id: u64,
+
+    /// when the batch is due to be signed
+    pub deadline: Instant,
+
+    /// the whole batch, if this request filled it
+    pub full: Option<Vec<Queued>>,
+}
+
+#[derive(Default)]
+struct BatchState {
+    queue: Vec<Queued>,
+    deadline: Option<Instant>,
+
+    /// id of the open batch; bumped whenever a batch is taken
+    id: u64,
+}
+
+/// Requests waiting to be signed together.
+///
+/// Every waiting request sleeps until its batch's deadline and whichever wakes
+/// first takes the batch, so a request whose caller goes away never strands
+/// the others.
+pub struct Batcher {
+    config: BatchConfig,
+    state: Mutex<BatchState>,
+}
+
+impl Batcher {
+    /// Create an empty batcher
+    pub fn new(config: BatchConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BatchState::default()),
+        }
+    }
+
+    /// Batching configuration in use
+    pub fn config(&self) -> BatchConfig {
+        self.config
+    }
+
+    /// Add a request to the open batch
+    pub fn enqueue(&self, request: TimestampRequest) -> Ticket {
+        let (reply, receiver) = oneshot::channel();
+        let mut state = self.state.lock().unwrap();
+        let deadline = *state
+            .deadline
+            .get_or_insert_with(|| Instant::now() + self.config.window);
+        let batch_id = state.id;
+        state.queue.push(Queued { request, reply });
+
+        let full = (state.queue.len() >= self.config.max_size).then(|| Self::drain(&mut state));
+        Ticket {
+            receiver,
+            batch_id,
+            deadline,
+            full,
+        }
+    }
+
+    /// Take batch `batch_id` if it is still open, leaving an empty one.
+    ///
+    /// Returns nothing once that batch has been taken, so a late waiter never
+    /// signs a newer batch before its deadline.
+    pub fn take(&self, batch_id: u64) -> Vec<Queued> {
+        let mut state = self.state.lock().unwrap();
+        if state.id != batch_id {
+            return Vec::new();
+        }
+        Self::drain(&mut state)
+    }
+
+    fn drain(state: &mut BatchState) -> Vec<Queued> {
+        state.deadline = None;
+        state.id = state.id.wrapping_add(1);
+        std::mem::take(&mut state.queue)
+    }
+}
+
+/// Deliver a failure to every request of a batch
+pub fn fail(batch: Vec<Queued>, error: &TimeServiceError) {
+    for queued in batch {
+        let _ = queued.reply.send(Err(error.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_late_waiter_does_not_take_a_newer_batch() {
+        let batcher = Batcher::new(BatchConfig::new(Duration::from_secs(1), 2));
+        let first = batcher.enqueue(TimestampRequest::new("a".to_string()));
+        let second = batcher.enqueue(TimestampRequest::new("b".to_string()));
+        assert_eq!(second.full.map(|batch| batch.len()), Some(2));
+
+        // a new batch opens; the first waiter's deadline passing must not drain it
+        let third = batcher.enqueue(TimestampRequest::new("c".to_string()));
+        assert_ne!(third.batch_id, first.batch_id);
+        assert!(batcher.take(first.batch_id).is_empty());
+        assert_eq!(batcher.take(third.batch_id).len(), 1);
+    }
+}
@@ -0,0 +1,84 @@
+// Canonical binary encoding for signed payloads
+
+/// Builder for unambiguous, domain-separated signing payloads.
+///
+/// Every payload starts with a length-prefixed domain label and a version
+/// byte, followed by tagged fields. Each field is written as
+/// `tag (u8) || length (u32 BE) || bytes`, so no two distinct field sets can
+/// produce the same byte string.
+pub struct CanonicalWriter {
+    buf: Vec<u8>,
+}
+
+impl CanonicalWriter {
+    /// Start a new payload for the given domain label and format version
+    pub fn new(domain: &str, version: u8) -> Self {
+        let mut writer = Self { buf: Vec::new() };
+        writer.write_len_prefixed(domain.as_bytes());
+        writer.buf.push(version);
+        writer
+    }
+
+    /// Append a tagged byte-string field
+    pub fn bytes(mut self, tag: u8, value: &[u8]) -> Self {
+        self.buf.push(tag);
+        self.write_len_prefixed(value);
+        self
+    }
+
+    /// Append a tagged UTF-8 string field
+    pub fn string(self, tag: u8, value: &str) -> Self {
+        self.bytes(tag, value.as_bytes())
+    }
+
+    /// Append a tagged unsigned integer field
+    pub fn u64(self, tag: u8, value: u64) -> Self {
+        self.bytes(tag, &value.to_be_bytes())
+    }
+
+    /// Append a tagged signed integer field
+    pub fn i64(self, tag: u8, value: i64) -> Self {
+        self.bytes(tag, &value.to_be_bytes())
+    }
+
+    /// Finish the payload and return the encoded bytes
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn write_len_prefixed(&mut self, value: &[u8]) {
+        self.buf
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.buf.extend_from_slice(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_are_unambiguous() {
+        // "ab" + "c" and "a" + "bc" must not collide
+        let first = CanonicalWriter::new("test", 1)
+            .string(1, "ab")
+            .string(2, "c")
+            .finish();
+        let second = CanonicalWriter::new("test", 1)
+            .string(1, "a")
+            .string(2, "bc")
+            .finish();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_domain_and_version_separate_payloads() {
+        let base = CanonicalWriter::new("test", 1).string(1, "x").finish();
+        let other_domain = CanonicalWriter::new("other", 1).string(1, "x").finish();
+        let other_version = CanonicalWriter::new("test", 2).string(1, "x").finish();
+
+        assert_ne!(base, other_domain);
+        assert_ne!(base, other_version);
+    }
+}
@@ -0,0 +1,448 @@
+// Append-only log of issued timestamps: hash-chained, and a Merkle transparency log
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::TimeServiceError;
+use crate::journal;
+use crate::merkle::{
+    ConsistencyProof, FileNodes, InclusionProof, MemoryNodes, MerkleLog, NodeStore,
+};
+use crate::models::AuthenticTimestamp;
+
+/// Entries kept in memory for range queries by default
+pub const DEFAULT_LOG_CAPACITY: usize = 65_536;
+
+/// Most entries returned by a single range query
+pub const MAX_LOG_RANGE: u64 = 1_000;
+
+/// Inclusive range of serial numbers to fetch from an authority's log
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LogRange {
+    /// first serial to return
+    pub from: u64,
+
+    /// last serial to return
+    pub to: u64,
+}
+
+/// Request for a proof that a timestamp is in the log at a given tree size
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InclusionRequest {
+    /// leaf hash of the timestamp (`AuthenticTimestamp::log_leaf_hash`)
+    pub leaf_hash: Vec<u8>,
+
+    /// position of the leaf, as given by an earlier inclusion proof; needed
+    /// once the entry is no longer among the most recent ones
+    #[serde(default)]
+    pub leaf_index: Option<u64>,
+
+    /// size of the tree head the proof is checked against
+    pub tree_size: u64,
+}
+
+/// Request for a proof that one tree head extends another
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConsistencyRequest {
+    /// size of the earlier tree
+    pub first: u64,
+
+    /// size of the later tree
+    pub second: u64,
+}
+
+struct Entries {
+    by_serial: BTreeMap<u64, AuthenticTimestamp>,
+    order: VecDeque<u64>,
+
+    /// every entry ever appended, as leaves of the transparency log
+    tree: MerkleLog<Box<dyn NodeStore>>,
+
+    /// positions of the most recent leaves; older ones must be asked for by index
+    leaf_indices: HashMap<Vec<u8>, u64>,
+    recent_leaves: VecDeque<Vec<u8>>,
+}
+
+/// The log file. Entries are queued in chain order under the head lock, then
+/// written and synced on the blocking pool, so no lock the issuing path
+/// takes is held across the fsync.
+struct LogJournal {
+    queued: Mutex<Vec<u8>>,
+    file: Mutex<File>,
+
+    /// set once a write fails: entries after a lost one would break the
+    /// chain on disk, so the log takes no more until it is reopened
+    failed: AtomicBool,
+}
+
+impl LogJournal {
+    // write and sync everything queued so far, including the caller's entry
+    // unless an earlier flush already made it durable
+    fn flush(&self) -> Result<(), TimeServiceError> {
+        let mut file = self.file.lock().unwrap();
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(TimeServiceError::generic(
+                "timestamp log write failed earlier; reopen the log",
+            ));
+        }
+
+        let queued = std::mem::take(&mut *self.queued.lock().unwrap());
+        if queued.is_empty() {
+            return Ok(());
+        }
+        let written = file.write_all(&queued).and_then(|_| file.sync_data());
+        if written.is_err() {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        Ok(written?)
+    }
+}
+
+/// The authority's log: every timestamp names the hash of the one issued before it.
+///
+/// Entries are appended one at a time, so the chain follows serial order. Every entry is also a leaf of a Merkle tree, so auditors can check
+/// signed tree heads, inclusion and consistency as in Certificate
+/// Transparency. Only the most recent `capacity` entries are kept in memory
+/// for range queries and leaf lookups; with a log file every entry is also
+/// appended to disk, and the tree's nodes are kept on disk beside it.
+pub struct TimestampLog {
+    /// held while an entry is built, which may wait on I/O such as reserving serials
+    appending: tokio::sync::Mutex<()>,
+    head: Mutex<Option<Vec<u8>>>,
+    entries: Mutex<Entries>,
+    journal: Option<Arc<LogJournal>>,
+    capacity: usize,
+}
+
+impl Default for TimestampLog {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_LOG_CAPACITY)
+    }
+}
+
+impl TimestampLog {
+    /// Create an in-memory log keeping the last `capacity` entries
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_tree(capacity, Box::new(MemoryNodes::default()))
+    }
+
+    fn with_tree(capacity: usize, nodes: Box<dyn NodeStore>) -> Self {
+        Self {
+            appending: tokio::sync::Mutex::new(()),
+            head: Mutex::new(None),
+            entries: Mutex::new(Entries {
+                by_serial: BTreeMap::new(),
+                order: VecDeque::new(),
+                tree: MerkleLog::with_store(nodes),
+                leaf_indices: HashMap::new(),
+                recent_leaves: VecDeque::new(),
+            }),
+            journal: None,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Open (or create) a log file and continue the chain from its last entry
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TimeServiceError> {
+        // the tree is rebuilt from the log into `<log>.tree/`, so it never disagrees with it
+        let path = path.as_ref();
+        let nodes = FileNodes::create(path.with_extension("tree"))?;
+        let mut log = Self::with_tree(DEFAULT_LOG_CAPACITY, Box::new(nodes));
+
+        let mut last = None;
+        let file = journal::open(path, |timestamp: AuthenticTimestamp| {
+            last = Some(timestamp.entry_hash()?);
+            log.remember(&mut log.entries.lock().unwrap(), timestamp)
+        })?;
+        *log.head.lock().unwrap() = last;
+        log.journal = Some(Arc::new(LogJournal {
+            queued: Mutex::new(Vec::new()),
+            file: Mutex::new(file),
+            failed: AtomicBool::new(false),
+        }));
+        Ok(log)
+    }
+
+    /// Build the next entry from the current head, record it and make it the new head.
+    ///
+    /// `build` receives the hash of the previous entry (None for the first)
+    /// and returns the signed entry. Appends wait for each other while `build`
+    /// runs, so serials taken inside it follow the chain order; if it fails
+    /// the head is unchanged. With a log file, the entry is returned once it
+    /// is synced to disk.
+    pub async fn append<F, B>(&self, build: F) -> Result<AuthenticTimestamp, TimeServiceError>
+    where
+        F: FnOnce(Option<Vec<u8>>) -> B,
+        B: Future<Output = Result<AuthenticTimestamp, TimeServiceError>>,
+    {
+        let timestamp = {
+            let _appending = self.appending.lock().await;
+            if let Some(journal) = &self.journal {
+                if journal.failed.load(Ordering::SeqCst) {
+                    return Err(TimeServiceError::generic(
+                        "timestamp log write failed earlier; reopen the log",
+                    ));
+                }
+            }
+            let timestamp = build(self.head()).await?;
+            let entry_hash = timestamp.entry_hash()?;
+
+            if let Some(journal) = &self.journal {
+                journal::append(&mut *journal.queued.lock().unwrap(), &timestamp)?;
+            }
+            self.remember(&mut self.entries.lock().unwrap(), timestamp.clone())?;
+            *self.head.lock().unwrap() = Some(entry_hash);
+            timestamp
+        };
+
+        // on disk before it is handed out, so a restart never forks the chain
+        if let Some(journal) = &self.journal {
+            let journal = journal.clone();
+            tokio::task::spawn_blocking(move || journal.flush())
+                .await
+                .map_err(|e| {
+                    TimeServiceError::generic(format!("log write task failed: {}", e))
+                })??;
+        }
+        Ok(timestamp)
+    }
+
+    fn remember(
+        &self,
+        entries: &mut Entries,
+        timestamp: AuthenticTimestamp,
+    ) -> Result<(), TimeServiceError> {
+        let leaf_hash = timestamp.log_leaf_hash()?;
+        let index = entries.tree.append(leaf_hash.clone())?;
+        entries.leaf_indices.insert(leaf_hash.clone(), index);
+        entries.recent_leaves.push_back(leaf_hash);
+        while entries.recent_leaves.len() > self.capacity {
+            if let Some(oldest) = entries.recent_leaves.pop_front() {
+                entries.leaf_indices.remove(&oldest);
+            }
+        }
+
+        let Some(serial) = timestamp.serial else {
+            return Ok(());
+        };
+        entries.by_serial.insert(serial, timestamp);
+        entries.order.push_back(serial);
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.by_serial.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash of the latest entry
+    pub fn head(&self) -> Option<Vec<u8>> {
+        self.head.lock().unwrap().clone()
+    }
+
+    /// Size and Merkle root of the transparency log
+    pub fn tree_head(&self) -> Result<(u64, Vec<u8>), TimeServiceError> {
+        let entries = self.entries.lock().unwrap();
+        let size = entries.tree.len();
+        Ok((size, entries.tree.root(size)?))
+    }
+
+    /// Proof that the entry with `request.leaf_hash` is in the tree of `request.tree_size` entries
+    ///
+    /// Only the most recent `capacity` leaves can be found by hash; older ones
+    /// must name their `leaf_index`, which is checked against the stored leaf.
+    /// Neither lookup scans the log, so requests can't stall issuance.
+    pub fn inclusion_proof(
+        &self,
+        request: &InclusionRequest,
+    ) -> Result<InclusionProof, TimeServiceError> {
+        let entries = self.entries.lock().unwrap();
+        let index = match request.leaf_index {
+            Some(index) if entries.tree.leaf(index)? == request.leaf_hash => Some(index),
+            Some(_) => None,
+            None => entries.leaf_indices.get(&request.leaf_hash).copied(),
+        }
+        .ok_or_else(|| TimeServiceError::InvalidProof("entry is not in the log".to_string()))?;
+        entries.tree.inclusion_proof(index, request.tree_size)
+    }
+
+    /// Proof that the tree of `request.first` entries is a prefix of the tree of `request.second`
+    pub fn consistency_proof(
+        &self,
+        request: ConsistencyRequest,
+    ) -> Result<ConsistencyProof, TimeServiceError> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .tree
+            .consistency_proof(request.first, request.second)
+    }
+
+    /// Entries with serials in `range` that are still held in memory, in serial order
+    pub fn range(&self, range: LogRange) -> Result<Vec<AuthenticTimestamp>, TimeServiceError> {
+        if range.from > range.to {
+            return Err(TimeServiceError::RequestRejected(
+                "log range ends before it starts".to_string(),
+            ));
+        }
+        if range.to - range.from >= MAX_LOG_RANGE {
+            return Err(TimeServiceError::RequestRejected(format!(
+                "log ranges are limited to {} entries",
+                MAX_LOG_RANGE
+            )));
+        }
+
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .by_serial
+            .range(range.from..=range.to)
+            .map(|(_, timestamp)| timestamp.clone())
+            .collect())
+    }
+}
+
+/// Check that `links` is an unbroken stretch of one authority replica's log: each
+/// entry names the hash of the one before it, serials increase and times never
+/// decrease. Signatures are not checked here.
+pub fn is_linked(links: &[AuthenticTimestamp]) -> Result<bool, TimeServiceError> {
+    for pair in links.windows(2) {
+        let (previous, next) = (&pair[0], &pair[1]);
+        let linked = next.authority_id == previous.authority_id
+            && next.replica_id == previous.replica_id
+            && next.previous_hash.as_deref() == Some(previous.entry_hash()?.as_slice())
+            && next.serial > previous.serial
+            && next.timestamp >= previous.timestamp;
+        if !linked {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CURRENT_PAYLOAD_VERSION;
+    use chrono::Utc;
+
+    fn entry(serial: u64, previous_hash: Option<Vec<u8>>) -> AuthenticTimestamp {
+        AuthenticTimestamp {
+            timestamp: Utc::now(),
+            nonce: format!("chain-nonce-{}", serial),
+            authority_id: "test.authority".to_string(),
+            message_imprint: None,
+            key_id: None,
+            replica_id: None,
+            serial: Some(serial),
+            previous_hash,
+            batch: None,
+            version: CURRENT_PAYLOAD_VERSION,
+            accuracy: None,
+            precision: None,
+            signature: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reopened_log_continues_the_chain() {
+        let path = std::env::temp_dir().join(format!("log-{:x}.jsonl", rand::random::<u64>()));
+
+        let head = {
+            let log = TimestampLog::open(&path).unwrap();
+            for serial in 1..=3 {
+                log.append(|previous_hash| async move { Ok(entry(serial, previous_hash)) })
+                    .await
+                    .unwrap();
+            }
+            log.head()
+        };
+
+        let log = TimestampLog::open(&path).unwrap();
+        assert_eq!(log.head(), head);
+        let next = log
+            .append(|previous_hash| async { Ok(entry(4, previous_hash)) })
+            .await
+            .unwrap();
+        assert_eq!(next.previous_hash, head);
+
+        let entries = log.range(LogRange { from: 1, to: 4 }).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(is_linked(&entries).unwrap());
+        // the transparency log is rebuilt from the file too
+        assert_eq!(log.tree_head().unwrap().0, 4);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir_all(path.with_extension("tree")).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_appends_reach_disk_in_chain_order() {
+        let path = std::env::temp_dir().join(format!("log-{:x}.jsonl", rand::random::<u64>()));
+        let log = Arc::new(TimestampLog::open(&path).unwrap());
+        let serials = Arc::new(std::sync::atomic::AtomicU64::new(1));
+
+        let appends = (0..32).map(|_| {
+            let (log, serials) = (log.clone(), serials.clone());
+            tokio::spawn(async move {
+                log.append(|previous_hash| async move {
+                    let serial = serials.fetch_add(1, Ordering::SeqCst);
+                    Ok(entry(serial, previous_hash))
+                })
+                .await
+                .unwrap()
+            })
+        });
+        for append in futures::future::join_all(appends).await {
+            append.unwrap();
+        }
+
+        let reopened = TimestampLog::open(&path).unwrap();
+        assert_eq!(reopened.head(), log.head());
+        let entries = reopened.range(LogRange { from: 1, to: 32 }).unwrap();
+        assert_eq!(entries.len(), 32);
+        assert!(is_linked(&entries).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir_all(path.with_extension("tree")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_old_entries_are_provable_by_index() {
+        let log = TimestampLog::with_capacity(2);
+        let mut appended = Vec::new();
+        for serial in 1..=5 {
+            appended.push(
+                log.append(|previous_hash| async move { Ok(entry(serial, previous_hash)) })
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(log.entries.lock().unwrap().leaf_indices.len(), 2);
+
+        // the first entry has left the index, so it must be asked for by position
+        let (size, root) = log.tree_head().unwrap();
+        let leaf_hash = appended[0].log_leaf_hash().unwrap();
+        let mut request = InclusionRequest {
+            leaf_hash: leaf_hash.clone(),
+            leaf_index: None,
+            tree_size: size,
+        };
+        assert!(log.inclusion_proof(&request).is_err());
+
+        request.leaf_index = Some(0);
+        let proof = log.inclusion_proof(&request).unwrap();
+        assert_eq!(proof.leaf_index, 0);
+        assert_eq!(proof.root_from(&leaf_hash).unwrap(), root);
+
+        // a position holding another entry, or none, proves nothing
+        request.leaf_index = Some(1);
+        assert!(log.inclusion_proof(&request).is_err());
+        request.leaf_index = Some(size);
+        assert!(log.inclusion_proof(&request).is_err());
+    }
+}
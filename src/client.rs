@@ -1,11 +1,52 @@
 // Client implementation for the Authentic Time Service
 
+use chrono::{DateTime, Utc};
 use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
 use rand::rngs::OsRng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::chain;
+use crate::clock::{ClockSource, SystemClock};
 use crate::error::TimeServiceError;
-use crate::models::{AuthenticTimestamp, TimestampRequest};
+use crate::imprint::{HashAlgorithm, MessageImprint};
+use crate::keys;
+use crate::merkle::{ConsistencyProof, InclusionProof};
+use crate::models::{
+    AuthenticTimestamp, AuthorityMetadata, KeyValidity, SignedTreeHead, TimestampRequest,
+    TimestampResponse,
+};
+use crate::pending::{PendingRequests, TrackedResponse};
+use crate::policy::{Verdict, VerificationPolicy};
+use crate::quorum::QuorumTimestamp;
+use crate::roughtime::{self, ChainLink, ChainResponse, ChainedEvidence, RoughtimeResponse};
+
+/// How long unanswered requests are tracked by default
+const DEFAULT_REQUEST_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// An authority signing key trusted by a client
+struct TrustedKey {
+    /// identifier of the key, matched against `AuthenticTimestamp::key_id`
+    key_id: String,
+
+    /// key used to verify signatures
+    public_key: PublicKey,
+
+    /// validity window of the key (unbounded where unknown)
+    valid_from: Option<DateTime<Utc>>,
+    valid_until: Option<DateTime<Utc>>,
+}
+
+impl TrustedKey {
+    fn covers(&self, time: DateTime<Utc>) -> bool {
+        self.valid_from.is_none_or(|from| time >= from)
+            && self.valid_until.is_none_or(|until| time <= until)
+    }
+}
 
 /// Client for interacting with time authorities
 pub struct TimeClient {
@@ -15,18 +56,32 @@ pub struct TimeClient {
     /// Client keypair for authentication
     keypair: Option<Keypair>,
 
-    /// Cache of known authority public keys
-    authority_keys: HashMap<String, PublicKey>,
+    /// Cache of known authority public keys (several per authority across rotations)
+    authority_keys: HashMap<String, Vec<TrustedKey>>,
+
+    /// Whether timestamps signed with the legacy payload format are accepted
+    accept_legacy: bool,
+
+    /// Hash algorithm used when timestamping data
+    hash_algorithm: HashAlgorithm,
+
+    /// Freshness and trust rules applied by `evaluate_timestamp`
+    policy: VerificationPolicy,
+
+    /// Requests sent to authorities and not yet answered
+    pending: PendingRequests,
+
+    /// How long an unanswered request is tracked before it is dropped
+    request_ttl: Duration,
+
+    /// Local clock timestamps are judged against
+    clock: Arc<dyn ClockSource>,
 }
 
 impl TimeClient {
     /// Create a new anonymous time client
     pub fn new_anonymous() -> Self {
-        Self {
-            id: None,
-            keypair: None,
-            authority_keys: HashMap::new(),
-        }
+        Self::from_parts(None, None)
     }
 
     /// Create a new authenticated time client
@@ -34,19 +89,25 @@ impl TimeClient {
         let mut csprng = OsRng {};
         let keypair = Keypair::generate(&mut csprng);
 
-        Self {
-            id: Some(id),
-            keypair: Some(keypair),
-            authority_keys: HashMap::new(),
-        }
+        Self::from_parts(Some(id), Some(keypair))
     }
 
     /// Create a new authenticated time client with an existing keypair
     pub fn with_keypair(id: String, keypair: Keypair) -> Self {
+        Self::from_parts(Some(id), Some(keypair))
+    }
+
+    fn from_parts(id: Option<String>, keypair: Option<Keypair>) -> Self {
         Self {
-            id: Some(id),
-            keypair: Some(keypair),
+            id,
+            keypair,
             authority_keys: HashMap::new(),
+            accept_legacy: false,
+            hash_algorithm: HashAlgorithm::Sha256,
+            policy: VerificationPolicy::default(),
+            pending: PendingRequests::new(),
+            request_ttl: DEFAULT_REQUEST_TTL,
+            clock: Arc::new(SystemClock),
         }
     }
 
@@ -57,7 +118,40 @@ impl TimeClient {
             .map(|kp| kp.public.to_bytes().to_vec())
     }
 
-    /// Add a known authority public key
+    /// Set whether legacy (version 0) timestamps are accepted during migration
+    ///
+    /// Off by default. Legacy timestamps sign only their time and nonce, so
+    /// they are never accepted with an imprint, serial or other bound field.
+    pub fn set_accept_legacy(&mut self, accept: bool) {
+        self.accept_legacy = accept;
+    }
+
+    /// Read local time from the given clock instead of the system clock
+    pub fn set_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        self.clock = clock;
+    }
+
+    /// Set how long an unanswered request is tracked before it is dropped
+    pub fn set_request_ttl(&mut self, ttl: Duration) {
+        self.request_ttl = ttl;
+    }
+
+    /// Set the hash algorithm used when timestamping data
+    pub fn set_hash_algorithm(&mut self, hash_algorithm: HashAlgorithm) {
+        self.hash_algorithm = hash_algorithm;
+    }
+
+    /// Set the verification policy applied by `evaluate_timestamp`
+    pub fn set_policy(&mut self, policy: VerificationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Get the verification policy applied by `evaluate_timestamp`
+    pub fn policy(&self) -> &VerificationPolicy {
+        &self.policy
+    }
+
+    /// Add a known authority public key (valid for any time)
     pub fn add_authority(
         &mut self,
         authority_id: String,
@@ -66,14 +160,65 @@ impl TimeClient {
         let public_key = PublicKey::from_bytes(public_key_bytes)
             .map_err(|_| TimeServiceError::InvalidSignature)?;
 
-        self.authority_keys.insert(authority_id, public_key);
+        self.insert_key(
+            authority_id,
+            TrustedKey {
+                key_id: keys::key_id(&public_key),
+                public_key,
+                valid_from: None,
+                valid_until: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Add an authority key restricted to its validity window
+    pub fn add_authority_key_with_validity(
+        &mut self,
+        authority_id: String,
+        key: &KeyValidity,
+    ) -> Result<(), TimeServiceError> {
+        let public_key = PublicKey::from_bytes(&key.public_key)
+            .map_err(|_| TimeServiceError::InvalidSignature)?;
+
+        self.insert_key(
+            authority_id,
+            TrustedKey {
+                key_id: key.key_id.clone(),
+                public_key,
+                valid_from: Some(key.valid_from),
+                valid_until: key.valid_until,
+            },
+        );
         Ok(())
     }
 
+    /// Trust every key published in an authority's metadata
+    pub fn add_authority_metadata(
+        &mut self,
+        metadata: &AuthorityMetadata,
+    ) -> Result<(), TimeServiceError> {
+        for key in &metadata.keys {
+            self.add_authority_key_with_validity(metadata.authority_id.clone(), key)?;
+        }
+        Ok(())
+    }
+
+    /// Insert or replace a trusted key for an authority
+    fn insert_key(&mut self, authority_id: String, key: TrustedKey) {
+        let keys = self.authority_keys.entry(authority_id).or_default();
+        keys.retain(|existing| existing.key_id != key.key_id);
+        keys.push(key);
+    }
+
     /// Generate a new timestamp request
     pub fn create_request(&self) -> TimestampRequest {
-        let nonce = format!("{:x}", rand::random::<u128>());
+        self.create_request_with_nonce(format!("{:x}", rand::random::<u128>()))
+    }
 
+    /// Generate a timestamp request carrying the given nonce, e.g. one chained
+    /// to a previous response
+    pub fn create_request_with_nonce(&self, nonce: String) -> TimestampRequest {
         // If we have a keypair, sign the request
         let (client_signature, client_id) = match (&self.keypair, &self.id) {
             (Some(kp), Some(id)) => {
@@ -87,7 +232,73 @@ impl TimeClient {
             nonce,
             client_signature,
             client_id,
+            message_imprint: None,
+            payload_version: None,
+        }
+    }
+
+    /// Generate a timestamp request for a byte slice
+    pub fn create_request_for_data(&self, data: &[u8]) -> TimestampRequest {
+        self.create_request()
+            .with_imprint(MessageImprint::from_data(self.hash_algorithm, data))
+    }
+
+    /// Generate a timestamp request for everything produced by a reader
+    pub fn create_request_for_reader<R: Read>(
+        &self,
+        reader: R,
+    ) -> Result<TimestampRequest, TimeServiceError> {
+        let imprint = MessageImprint::from_reader(self.hash_algorithm, reader)?;
+        Ok(self.create_request().with_imprint(imprint))
+    }
+
+    /// Generate a timestamp request for a file's contents
+    pub fn create_request_for_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<TimestampRequest, TimeServiceError> {
+        self.create_request_for_reader(File::open(path)?)
+    }
+
+    /// Record that a request is being sent to an authority
+    pub fn track_request(
+        &self,
+        request: &TimestampRequest,
+        authority_id: &str,
+    ) -> Result<(), TimeServiceError> {
+        // requests whose sender gave up never complete; sweep them as new ones go out
+        self.pending.expire(self.request_ttl);
+        self.pending.track(request, authority_id)
+    }
+
+    /// Forget a tracked request that failed before a response arrived
+    pub fn cancel_request(&self, nonce: &str) {
+        self.pending.cancel(nonce);
+    }
+
+    /// Match a response to its tracked request, rejecting unsolicited,
+    /// duplicated, mismatched or badly signed responses, and measure the round trip.
+    ///
+    /// The request is only consumed once the response passes every check, so
+    /// a forged reply can't use it up. Refusals are accepted if signed.
+    pub fn accept_response(
+        &self,
+        response: &TimestampResponse,
+    ) -> Result<TrackedResponse, TimeServiceError> {
+        self.pending.check(&response.timestamp)?;
+        let signed = match response.is_success() {
+            true => self.verify_timestamp(&response.timestamp)?,
+            false => self.verify_response(response)?,
+        };
+        if !signed {
+            return Err(TimeServiceError::InvalidSignature);
         }
+        self.pending.complete(response.timestamp.clone())
+    }
+
+    /// Number of tracked requests still awaiting a response
+    pub fn outstanding_requests(&self) -> usize {
+        self.pending.outstanding()
     }
 
     /// Verify a timestamp from an authority
@@ -95,33 +306,386 @@ impl TimeClient {
         &self,
         timestamp: &AuthenticTimestamp,
     ) -> Result<bool, TimeServiceError> {
-        // Look up the authority's public key
-        let pubkey = match self.authority_keys.get(&timestamp.authority_id) {
-            Some(pk) => pk,
+        self.verify_signed(timestamp, &timestamp.signing_payload()?)
+    }
+
+    /// Verify an authority's response, including the signature on a refusal
+    pub fn verify_response(&self, response: &TimestampResponse) -> Result<bool, TimeServiceError> {
+        self.verify_signed(&response.timestamp, &response.signing_payload()?)
+    }
+
+    // check the timestamp's signature over `message` with the authority key it names
+    fn verify_signed(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        message: &[u8],
+    ) -> Result<bool, TimeServiceError> {
+        let candidates = self.signing_keys(
+            &timestamp.authority_id,
+            timestamp.key_id.as_deref(),
+            timestamp.timestamp,
+        )?;
+
+        // Reject the legacy format once migration is complete
+        if timestamp.is_legacy() && !self.accept_legacy {
+            return Err(TimeServiceError::UnsupportedVersion(timestamp.version));
+        }
+
+        Self::any_key_verifies(&candidates, message, &timestamp.signature)
+    }
+
+    // the authority keys that may have signed at `at`: the named one, or every
+    // key valid then for data that doesn't name one
+    fn signing_keys(
+        &self,
+        authority_id: &str,
+        key_id: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> Result<Vec<&TrustedKey>, TimeServiceError> {
+        // Look up the authority's public keys
+        let keys = match self.authority_keys.get(authority_id) {
+            Some(keys) => keys,
             None => {
                 return Err(TimeServiceError::AuthorityNotFound(
-                    timestamp.authority_id.clone(),
+                    authority_id.to_string(),
                 ))
             }
         };
 
-        // Create message that was signed
-        let message = timestamp.format_message();
+        let candidates: Vec<&TrustedKey> = match key_id {
+            Some(key_id) => {
+                let key = keys
+                    .iter()
+                    .find(|key| key.key_id == key_id)
+                    .ok_or_else(|| TimeServiceError::UnknownKey(key_id.to_string()))?;
+                if !key.covers(at) {
+                    return Err(TimeServiceError::KeyNotValidAt(key_id.to_string()));
+                }
+                vec![key]
+            }
+            None => keys.iter().filter(|key| key.covers(at)).collect(),
+        };
+        if candidates.is_empty() {
+            return Err(TimeServiceError::KeyNotValidAt(authority_id.to_string()));
+        }
+        Ok(candidates)
+    }
 
+    fn any_key_verifies(
+        candidates: &[&TrustedKey],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, TimeServiceError> {
         // Create signature object
-        let signature = Signature::from_bytes(&timestamp.signature)
-            .map_err(|_| TimeServiceError::InvalidSignature)?;
+        let signature =
+            Signature::from_bytes(signature).map_err(|_| TimeServiceError::InvalidSignature)?;
 
         // Verify signature
-        Ok(pubkey.verify(message.as_bytes(), &signature).is_ok())
+        Ok(candidates
+            .iter()
+            .any(|key| key.public_key.verify(message, &signature).is_ok()))
+    }
+
+    /// Verify the signature on an authority's transparency log tree head
+    pub fn verify_tree_head(&self, head: &SignedTreeHead) -> Result<bool, TimeServiceError> {
+        let candidates =
+            self.signing_keys(&head.authority_id, head.key_id.as_deref(), head.timestamp)?;
+        Self::any_key_verifies(&candidates, &head.signing_payload(), &head.signature)
+    }
+
+    /// Verify that a timestamp is in the authority's log as of a signed tree head
+    pub fn verify_inclusion(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        proof: &InclusionProof,
+        head: &SignedTreeHead,
+    ) -> Result<bool, TimeServiceError> {
+        if timestamp.authority_id != head.authority_id || proof.tree_size != head.tree_size {
+            return Ok(false);
+        }
+        if !self.verify_tree_head(head)? || !self.verify_timestamp(timestamp)? {
+            return Ok(false);
+        }
+        Ok(proof.root_from(&timestamp.log_leaf_hash()?)? == head.root_hash)
+    }
+
+    /// Verify that `newer` extends `older` without rewriting any entry
+    pub fn verify_consistency(
+        &self,
+        older: &SignedTreeHead,
+        newer: &SignedTreeHead,
+        proof: &ConsistencyProof,
+    ) -> Result<bool, TimeServiceError> {
+        if older.authority_id != newer.authority_id
+            || proof.first_size != older.tree_size
+            || proof.second_size != newer.tree_size
+        {
+            return Ok(false);
+        }
+        if !self.verify_tree_head(older)? || !self.verify_tree_head(newer)? {
+            return Ok(false);
+        }
+        proof.verify(&older.root_hash, &newer.root_hash)
+    }
+
+    /// Verify a Roughtime response to `nonce` against the authority's trusted keys
+    pub fn verify_roughtime_response(
+        &self,
+        authority_id: &str,
+        nonce: &[u8],
+        response: &[u8],
+    ) -> Result<RoughtimeResponse, TimeServiceError> {
+        let keys = self
+            .authority_keys
+            .get(authority_id)
+            .ok_or_else(|| TimeServiceError::AuthorityNotFound(authority_id.to_string()))?;
+
+        // Roughtime names no key, so try each; the time must fall in the key's window
+        let mut error = TimeServiceError::InvalidSignature;
+        for key in keys {
+            match roughtime::verify_response(&key.public_key, authority_id, nonce, response) {
+                Ok(verified) if key.covers(verified.midpoint) => return Ok(verified),
+                Ok(_) => error = TimeServiceError::KeyNotValidAt(key.key_id.clone()),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    /// Ask a Roughtime server for the time with the given nonce and verify the answer
+    pub async fn request_roughtime(
+        &self,
+        authority_id: &str,
+        addr: &str,
+        nonce: &[u8; roughtime::NONCE_SIZE],
+    ) -> Result<RoughtimeResponse, TimeServiceError> {
+        let response = roughtime::query(
+            addr,
+            &roughtime::request(nonce),
+            roughtime::DEFAULT_QUERY_TIMEOUT,
+        )
+        .await?;
+        self.verify_roughtime_response(authority_id, nonce, &response)
+    }
+
+    /// Query Roughtime authorities (id, address) in turn, deriving each nonce from the previous response
+    pub async fn request_roughtime_chain(
+        &self,
+        authorities: &[(&str, &str)],
+    ) -> Result<ChainedEvidence, TimeServiceError> {
+        let mut evidence = ChainedEvidence::default();
+        for (authority_id, addr) in authorities {
+            let blind = roughtime::random_nonce();
+            let nonce = evidence.next_nonce(&blind)?;
+            let response = self.request_roughtime(authority_id, addr, &nonce).await?;
+            evidence.links.push(ChainLink {
+                authority_id: authority_id.to_string(),
+                blind: blind.to_vec(),
+                response: ChainResponse::Roughtime(response.raw),
+            });
+        }
+        Ok(evidence)
+    }
+
+    /// Verify chained evidence offline, returning its responses in the order they were obtained
+    pub fn verify_roughtime_chain(
+        &self,
+        evidence: &ChainedEvidence,
+    ) -> Result<Vec<RoughtimeResponse>, TimeServiceError> {
+        // a response only answers the nonce derived from the one before it
+        let nonces = evidence.expected_nonces()?;
+        let mut responses = Vec::with_capacity(evidence.links.len());
+        for (i, (link, nonce)) in evidence.links.iter().zip(&nonces).enumerate() {
+            let ChainResponse::Roughtime(raw) = &link.response else {
+                return Err(TimeServiceError::InvalidProof(format!(
+                    "link {} is not a Roughtime response",
+                    i
+                )));
+            };
+            responses.push(self.verify_roughtime_response(&link.authority_id, nonce, raw)?);
+        }
+        Ok(responses)
+    }
+
+    /// Verify chained TSP timestamps offline, returning them in the order they were issued
+    pub fn verify_timestamp_chain(
+        &self,
+        evidence: &ChainedEvidence,
+    ) -> Result<Vec<AuthenticTimestamp>, TimeServiceError> {
+        // a timestamp only answers the nonce derived from the one before it
+        let nonces = evidence.expected_nonces()?;
+        let mut timestamps = Vec::with_capacity(evidence.links.len());
+        for (i, (link, nonce)) in evidence.links.iter().zip(&nonces).enumerate() {
+            let ChainResponse::Timestamp(timestamp) = &link.response else {
+                return Err(TimeServiceError::InvalidProof(format!(
+                    "link {} is not a timestamp",
+                    i
+                )));
+            };
+            if timestamp.authority_id != link.authority_id
+                || timestamp.nonce != roughtime::request_nonce(nonce)
+            {
+                return Err(TimeServiceError::InvalidProof(format!(
+                    "link {} doesn't answer its chained nonce",
+                    i
+                )));
+            }
+            if !self.verify_timestamp(timestamp)? {
+                return Err(TimeServiceError::InvalidSignature);
+            }
+            timestamps.push(timestamp.as_ref().clone());
+        }
+        Ok(timestamps)
+    }
+
+    /// Check a timestamp's signature and freshness against the client's policy
+    pub fn evaluate_timestamp(&self, timestamp: &AuthenticTimestamp) -> Verdict {
+        self.evaluate_timestamp_at(timestamp, self.clock.now())
+    }
+
+    /// Check a timestamp against the client's policy as of the given local time
+    pub fn evaluate_timestamp_at(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        now: DateTime<Utc>,
+    ) -> Verdict {
+        if !self.policy.allows_authority(&timestamp.authority_id) {
+            return Verdict::UnknownAuthority(timestamp.authority_id.clone());
+        }
+
+        match self.verify_timestamp(timestamp) {
+            Ok(true) => {}
+            Ok(false) => return Verdict::BadSignature("signature does not verify".to_string()),
+            Err(TimeServiceError::AuthorityNotFound(id)) => return Verdict::UnknownAuthority(id),
+            Err(e) => return Verdict::BadSignature(e.to_string()),
+        }
+
+        if let Some(max_accuracy) = self.policy.max_accuracy {
+            let accuracy = timestamp.accuracy.map(|accuracy| accuracy.as_duration());
+            if accuracy.is_none_or(|accuracy| accuracy > max_accuracy) {
+                return Verdict::Inaccurate { accuracy };
+            }
+        }
+
+        match (now - timestamp.timestamp).to_std() {
+            // timestamp is in the past
+            Ok(age) => match self.policy.max_age {
+                Some(max_age) if age > max_age => Verdict::Stale { age },
+                _ => Verdict::Valid,
+            },
+            // timestamp is ahead of the local clock
+            Err(_) => {
+                let ahead = (timestamp.timestamp - now).to_std().unwrap_or_default();
+                if ahead > self.policy.max_future_skew {
+                    Verdict::FromTheFuture { ahead }
+                } else {
+                    Verdict::Valid
+                }
+            }
+        }
+    }
+
+    /// Verify a quorum bundle: every timestamp must verify and vouch for the
+    /// same data, and at least `threshold` distinct authorities must agree.
+    ///
+    /// Agreement is recomputed from the signed times and accuracies alone,
+    /// allowing the authorities to have been asked up to `max_rtt` apart (see
+    /// `QuorumTimestamp::signed_consensus`); the bundle's consensus time must
+    /// lie in the interval they agree on.
+    pub fn verify_quorum_timestamp(
+        &self,
+        bundle: &QuorumTimestamp,
+        threshold: usize,
+        max_rtt: Duration,
+    ) -> Result<bool, TimeServiceError> {
+        for timestamp in &bundle.timestamps {
+            if !self.verify_timestamp(timestamp)? {
+                return Ok(false);
+            }
+        }
+        if !bundle.shares_imprint() {
+            return Ok(false);
+        }
+
+        let Some(consensus) = bundle.signed_consensus(max_rtt) else {
+            return Ok(false);
+        };
+        let agreeing = consensus.agreeing.iter().collect::<HashSet<_>>().len();
+        Ok(agreeing >= threshold.max(1)
+            && consensus.earliest <= bundle.consensus_time
+            && bundle.consensus_time <= consensus.latest
+            && bundle.is_consistent())
+    }
+
+    /// Verify that `earlier` was issued before `later` by the same authority.
+    ///
+    /// `links` is the authority's log from `earlier` to `later` inclusive (see
+    /// `TspTimeService::get_log_range`); every entry must verify and name the
+    /// hash of the one before it.
+    pub fn verify_chain(
+        &self,
+        earlier: &AuthenticTimestamp,
+        later: &AuthenticTimestamp,
+        links: &[AuthenticTimestamp],
+    ) -> Result<bool, TimeServiceError> {
+        let (Some(first), Some(last)) = (links.first(), links.last()) else {
+            return Ok(false);
+        };
+        // the ends of the range must be the two timestamps being compared
+        if first.entry_hash()? != earlier.entry_hash()?
+            || last.entry_hash()? != later.entry_hash()?
+        {
+            return Ok(false);
+        }
+
+        for timestamp in links {
+            if !self.verify_timestamp(timestamp)? {
+                return Ok(false);
+            }
+        }
+
+        chain::is_linked(links)
+    }
+
+    /// Verify a timestamp and check that it vouches for the given data
+    pub fn verify_timestamp_for_data(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        data: &[u8],
+    ) -> Result<bool, TimeServiceError> {
+        Ok(timestamp.covers_data(data) && self.verify_timestamp(timestamp)?)
+    }
+
+    /// Verify a timestamp and check that it vouches for everything produced by a reader
+    pub fn verify_timestamp_for_reader<R: Read>(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        reader: R,
+    ) -> Result<bool, TimeServiceError> {
+        let imprint = match &timestamp.message_imprint {
+            Some(imprint) => imprint,
+            None => return Ok(false),
+        };
+
+        let digest = imprint.hash_algorithm.digest_reader(reader)?;
+        Ok(digest == imprint.hashed_message && self.verify_timestamp(timestamp)?)
+    }
+
+    /// Verify a timestamp and check that it vouches for a file's contents
+    pub fn verify_timestamp_for_file<P: AsRef<Path>>(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        path: P,
+    ) -> Result<bool, TimeServiceError> {
+        self.verify_timestamp_for_reader(timestamp, File::open(path)?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::authority::TimeAuthorityImpl;
-    use crate::models::TimestampRequest;
+    use crate::authority::{TimeAuthority, TimeAuthorityImpl};
+    use crate::models::{TimestampRequest, PAYLOAD_VERSION_LEGACY};
 
     #[test]
     fn test_client_request_creation() {
@@ -133,6 +697,50 @@ mod tests {
         assert!(!request.nonce.is_empty());
     }
 
+    #[test]
+    fn test_abandoned_requests_are_swept_on_send() {
+        let mut client = TimeClient::new_anonymous();
+        client.set_request_ttl(Duration::from_millis(10));
+
+        client
+            .track_request(&client.create_request(), "authority.a")
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        client
+            .track_request(&client.create_request(), "authority.a")
+            .unwrap();
+        assert_eq!(client.outstanding_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forged_responses_leave_the_request_open() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        let request = client.create_request_for_data(b"awaited");
+        client.track_request(&request, "test.authority").unwrap();
+        let response = authority.issue_timestamp(request).await.unwrap();
+
+        let mut forged = response.clone();
+        forged.timestamp.timestamp += chrono::Duration::days(1);
+        assert!(matches!(
+            client.accept_response(&forged),
+            Err(TimeServiceError::InvalidSignature)
+        ));
+        assert_eq!(client.outstanding_requests(), 1);
+
+        // the genuine response still completes the request, once
+        let tracked = client.accept_response(&response).unwrap();
+        assert_eq!(tracked.timestamp, response.timestamp);
+        assert!(matches!(
+            client.accept_response(&response),
+            Err(TimeServiceError::DuplicateResponse(_))
+        ));
+    }
+
     #[test]
     fn test_anonymous_client_request_creation() {
         let client = TimeClient::new_anonymous();
@@ -142,4 +750,227 @@ mod tests {
         assert!(request.client_signature.is_none());
         assert!(!request.nonce.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_verify_canonical_and_legacy_timestamps() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        let response = authority
+            .issue_timestamp(client.create_request())
+            .await
+            .unwrap();
+        assert!(client.verify_timestamp(&response.timestamp).unwrap());
+
+        // Tampering with the nonce breaks the canonical signature
+        let mut tampered = response.timestamp.clone();
+        tampered.nonce.push('0');
+        assert!(!client.verify_timestamp(&tampered).unwrap());
+
+        // Legacy timestamps are refused unless migration is still under way
+        let mut legacy = response.timestamp;
+        legacy.version = PAYLOAD_VERSION_LEGACY;
+        assert!(matches!(
+            client.verify_timestamp(&legacy),
+            Err(TimeServiceError::UnsupportedVersion(PAYLOAD_VERSION_LEGACY))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_timestamps_cannot_carry_unsigned_fields() {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), keypair.public.as_bytes())
+            .unwrap();
+        client.set_accept_legacy(true);
+
+        // a genuine legacy timestamp signs only its time and nonce
+        let mut legacy = AuthenticTimestamp {
+            timestamp: Utc::now(),
+            nonce: "legacy-nonce".to_string(),
+            authority_id: "test.authority".to_string(),
+            message_imprint: None,
+            key_id: None,
+            replica_id: None,
+            serial: None,
+            previous_hash: None,
+            batch: None,
+            version: PAYLOAD_VERSION_LEGACY,
+            accuracy: None,
+            precision: None,
+            signature: vec![],
+        };
+        legacy.signature = keypair
+            .sign(legacy.format_message().as_bytes())
+            .to_bytes()
+            .to_vec();
+        assert!(client.verify_timestamp(&legacy).unwrap());
+
+        // so an imprint attached afterwards is not vouched for
+        let mut forged = legacy.clone();
+        forged.message_imprint = Some(MessageImprint::from_data(
+            HashAlgorithm::Sha256,
+            b"forged document",
+        ));
+        assert!(client.verify_timestamp(&forged).is_err());
+        assert!(client
+            .verify_timestamp_for_data(&forged, b"forged document")
+            .is_err());
+
+        let mut forged = legacy;
+        forged.serial = Some(7);
+        assert!(client.verify_timestamp(&forged).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_and_verify_data() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        let document = b"the quick brown fox".to_vec();
+        let request = client.create_request_for_reader(&document[..]).unwrap();
+        let response = authority.issue_timestamp(request).await.unwrap();
+
+        assert!(client
+            .verify_timestamp_for_data(&response.timestamp, &document)
+            .unwrap());
+        assert!(client
+            .verify_timestamp_for_reader(&response.timestamp, &document[..])
+            .unwrap());
+        assert!(!client
+            .verify_timestamp_for_data(&response.timestamp, b"the lazy dog")
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rotated_keys_and_validity_windows() {
+        let mut authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let old = authority
+            .issue_timestamp(TimestampRequest::new("old-key".to_string()))
+            .await
+            .unwrap()
+            .timestamp;
+        authority.rotate_key(
+            Keypair::generate(&mut OsRng {}),
+            std::time::Duration::from_secs(0),
+        );
+        let new = authority
+            .issue_timestamp(TimestampRequest::new("new-key".to_string()))
+            .await
+            .unwrap()
+            .timestamp;
+
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority_metadata(&authority.metadata())
+            .unwrap();
+        assert!(client.verify_timestamp(&old).unwrap());
+        assert!(client.verify_timestamp(&new).unwrap());
+
+        // A retired key cannot vouch for times after its retirement
+        let retired = authority.key_history()[1].clone();
+        let mut forged = old.clone();
+        forged.timestamp = retired.valid_until.unwrap() + chrono::Duration::seconds(1);
+        assert!(matches!(
+            client.verify_timestamp(&forged),
+            Err(TimeServiceError::KeyNotValidAt(_))
+        ));
+
+        // Unknown key identifiers are rejected
+        let mut unknown = new;
+        unknown.key_id = Some("0000000000000000".to_string());
+        assert!(matches!(
+            client.verify_timestamp(&unknown),
+            Err(TimeServiceError::UnknownKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_policy_verdicts() {
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+        client.set_policy(
+            VerificationPolicy::new()
+                .with_max_age(std::time::Duration::from_secs(300))
+                .with_max_future_skew(std::time::Duration::from_secs(2)),
+        );
+
+        let timestamp = authority
+            .issue_timestamp(client.create_request())
+            .await
+            .unwrap()
+            .timestamp;
+        let issued = timestamp.timestamp;
+
+        assert_eq!(client.evaluate_timestamp(&timestamp), Verdict::Valid);
+        assert!(matches!(
+            client.evaluate_timestamp_at(&timestamp, issued + chrono::Duration::minutes(10)),
+            Verdict::Stale { .. }
+        ));
+        assert!(matches!(
+            client.evaluate_timestamp_at(&timestamp, issued - chrono::Duration::seconds(10)),
+            Verdict::FromTheFuture { .. }
+        ));
+
+        let mut tampered = timestamp.clone();
+        tampered.nonce.push('x');
+        assert!(matches!(
+            client.evaluate_timestamp(&tampered),
+            Verdict::BadSignature(_)
+        ));
+
+        client.set_policy(
+            VerificationPolicy::new()
+                .with_required_authorities(vec!["other.authority".to_string()]),
+        );
+        assert_eq!(
+            client.evaluate_timestamp(&timestamp),
+            Verdict::UnknownAuthority("test.authority".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_chain_proves_order() {
+        use crate::chain::LogRange;
+
+        let authority = TimeAuthorityImpl::new("test.authority".to_string());
+        let mut client = TimeClient::new_anonymous();
+        client
+            .add_authority("test.authority".to_string(), &authority.get_public_key())
+            .unwrap();
+
+        for _ in 0..4 {
+            authority
+                .issue_timestamp(client.create_request())
+                .await
+                .unwrap();
+        }
+        let links = authority.log_range(LogRange { from: 1, to: 4 }).unwrap();
+        assert_eq!(links.len(), 4);
+        let (earlier, later) = (&links[0], &links[3]);
+        assert!(client.verify_chain(earlier, later, &links).unwrap());
+
+        // the chain only runs forwards
+        let reversed: Vec<_> = links.iter().rev().cloned().collect();
+        assert!(!client.verify_chain(later, earlier, &reversed).unwrap());
+
+        // a missing link breaks the chain
+        let gapped = [links[0].clone(), links[2].clone(), links[3].clone()];
+        assert!(!client.verify_chain(earlier, later, &gapped).unwrap());
+
+        // so does an entry rewritten after it was issued
+        let mut tampered = links.clone();
+        tampered[1].previous_hash = Some(vec![0; 32]);
+        assert!(!client.verify_chain(earlier, later, &tampered).unwrap());
+    }
 }
@@ -0,0 +1,106 @@
+// Clock sources used for issuing and checking timestamps
+
+use chrono::{DateTime, Duration, Utc};
+use std::sync::{Arc, Mutex};
+
+/// Source of the current wall-clock time
+pub trait ClockSource: Send + Sync {
+    /// Current time according to this clock
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The operating system's clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at `now`
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Jump to the given time (forwards or backwards)
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Move the clock by `by`, which may be negative
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// A clock that reads another clock and applies a correction, e.g. the offset
+/// measured against a reference time source
+pub struct OffsetClock {
+    inner: Arc<dyn ClockSource>,
+    offset: Mutex<Duration>,
+}
+
+impl OffsetClock {
+    /// Wrap `inner`, adding `offset` to every reading
+    pub fn new(inner: Arc<dyn ClockSource>, offset: Duration) -> Self {
+        Self {
+            inner,
+            offset: Mutex::new(offset),
+        }
+    }
+
+    /// Correction currently applied
+    pub fn offset(&self) -> Duration {
+        *self.offset.lock().unwrap()
+    }
+
+    /// Replace the correction, e.g. after a new measurement
+    pub fn set_offset(&self, offset: Duration) {
+        *self.offset.lock().unwrap() = offset;
+    }
+}
+
+impl ClockSource for OffsetClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.inner.now() + self.offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_offset_clock_follows_manual_clock() {
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let manual = Arc::new(ManualClock::new(start));
+        let offset = OffsetClock::new(manual.clone(), Duration::milliseconds(-250));
+
+        assert_eq!(offset.now(), start - Duration::milliseconds(250));
+
+        manual.advance(Duration::seconds(10));
+        offset.set_offset(Duration::milliseconds(100));
+        assert_eq!(
+            offset.now(),
+            start + Duration::seconds(10) + Duration::milliseconds(100)
+        );
+    }
+}
@@ -0,0 +1,187 @@
+// Intersection-based (Marzullo) consensus time across several authorities
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One authority's reported time together with the uncertainty of the observation
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TimeSample {
+    /// authority that reported the time
+    pub authority_id: String,
+
+    /// time reported by the authority
+    pub time: DateTime<Utc>,
+
+    /// round-trip time of the request, in microseconds
+    pub rtt_micros: u64,
+
+    /// local time elapsed between receiving this response and the common
+    /// reference instant all samples are projected to, in microseconds
+    pub age_micros: u64,
+
+    /// accuracy declared by the authority (±), in microseconds
+    pub accuracy_micros: u64,
+}
+
+impl TimeSample {
+    /// Interval that contains the true time at the reference instant.
+    ///
+    /// The authority read its clock somewhere within the round trip, so at the
+    /// moment the response arrived the true time was between `time` and
+    /// `time + rtt`, widened by the declared accuracy and shifted by the
+    /// sample's age.
+    ///
+    /// Returns `None` if the interval runs past the representable range of
+    /// times, which no honest authority's accuracy or round trip does.
+    pub fn interval(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let at_reference = self.time.checked_add_signed(micros(self.age_micros))?;
+        let accuracy = micros(self.accuracy_micros);
+
+        Some((
+            at_reference.checked_sub_signed(accuracy)?,
+            at_reference
+                .checked_add_signed(micros(self.rtt_micros))?
+                .checked_add_signed(accuracy)?,
+        ))
+    }
+}
+
+/// Result of intersecting the confidence intervals of several samples
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Consensus {
+    /// earliest time consistent with the largest agreeing group
+    pub earliest: DateTime<Utc>,
+
+    /// latest time consistent with the largest agreeing group
+    pub latest: DateTime<Utc>,
+
+    /// authorities whose intervals contain the agreed interval
+    pub agreeing: Vec<String>,
+
+    /// authorities whose intervals fall outside the agreed interval
+    pub suspected_faulty: Vec<String>,
+}
+
+impl Consensus {
+    /// Midpoint of the agreed interval, used as the consensus time
+    pub fn time(&self) -> DateTime<Utc> {
+        self.earliest + (self.latest - self.earliest) / 2
+    }
+
+    /// Width of the agreed interval
+    pub fn uncertainty(&self) -> Duration {
+        self.latest - self.earliest
+    }
+}
+
+/// Run Marzullo's algorithm over the samples' confidence intervals.
+///
+/// Finds the smallest interval consistent with the largest number of sources;
+/// sources whose interval does not intersect it, or has no representable
+/// bounds, are reported as suspected faulty.
+pub fn marzullo(samples: &[TimeSample]) -> Option<Consensus> {
+    // (time, +1 for an interval start / -1 for an interval end)
+    let mut edges: Vec<(DateTime<Utc>, i32)> = Vec::with_capacity(samples.len() * 2);
+    for (lo, hi) in samples.iter().filter_map(TimeSample::interval) {
+        edges.push((lo, 1));
+        edges.push((hi, -1));
+    }
+    // starts sort before ends at the same instant so touching intervals overlap
+    edges.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut best = 0;
+    let mut count = 0;
+    let mut bounds = None;
+    for (i, (time, kind)) in edges.iter().enumerate() {
+        count += kind;
+        if count > best {
+            best = count;
+            bounds = Some((*time, edges[i + 1].0));
+        }
+    }
+
+    let (earliest, latest) = bounds?;
+    let (agreeing, suspected_faulty) = samples.iter().partition::<Vec<&TimeSample>, _>(|sample| {
+        sample
+            .interval()
+            .is_some_and(|(lo, hi)| lo <= earliest && hi >= latest)
+    });
+
+    Some(Consensus {
+        earliest,
+        latest,
+        agreeing: agreeing.iter().map(|s| s.authority_id.clone()).collect(),
+        suspected_faulty: suspected_faulty
+            .iter()
+            .map(|s| s.authority_id.clone())
+            .collect(),
+    })
+}
+
+fn micros(value: u64) -> Duration {
+    Duration::microseconds(value.min(i64::MAX as u64) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(id: &str, offset_ms: i64, rtt_ms: u64) -> TimeSample {
+        TimeSample {
+            authority_id: id.to_string(),
+            time: Utc.timestamp_opt(1_700_000_000, 0).unwrap() + Duration::milliseconds(offset_ms),
+            rtt_micros: rtt_ms * 1000,
+            age_micros: 0,
+            accuracy_micros: 0,
+        }
+    }
+
+    #[test]
+    fn test_intersection_excludes_falseticker() {
+        let samples = vec![
+            sample("a", 0, 40),
+            sample("b", 10, 40),
+            sample("c", 20, 40),
+            sample("faulty", 5_000, 40),
+        ];
+
+        let consensus = marzullo(&samples).unwrap();
+        let base = samples[0].time;
+
+        // a: [0, 40], b: [10, 50], c: [20, 60] -> agreed [20, 40]
+        assert_eq!(consensus.earliest, base + Duration::milliseconds(20));
+        assert_eq!(consensus.latest, base + Duration::milliseconds(40));
+        assert_eq!(consensus.time(), base + Duration::milliseconds(30));
+        assert_eq!(consensus.agreeing, vec!["a", "b", "c"]);
+        assert_eq!(consensus.suspected_faulty, vec!["faulty"]);
+    }
+
+    #[test]
+    fn test_accuracy_and_age_widen_and_shift_intervals() {
+        let mut widened = sample("a", 0, 10);
+        widened.accuracy_micros = 5_000;
+        widened.age_micros = 1_000;
+        let (lo, hi) = widened.interval().unwrap();
+
+        assert_eq!(hi - lo, Duration::milliseconds(20));
+        assert_eq!(lo, widened.time - Duration::milliseconds(4));
+    }
+
+    #[test]
+    fn test_unrepresentable_intervals_are_faulty() {
+        let mut huge_accuracy = sample("huge.accuracy", 0, 40);
+        huge_accuracy.accuracy_micros = u64::MAX;
+        let mut huge_rtt = sample("huge.rtt", 0, 40);
+        huge_rtt.rtt_micros = u64::MAX;
+        assert!(huge_accuracy.interval().is_none());
+        assert!(huge_rtt.interval().is_none());
+
+        let consensus = marzullo(&[sample("a", 0, 40), huge_accuracy, huge_rtt]).unwrap();
+        assert_eq!(consensus.agreeing, vec!["a"]);
+        assert_eq!(
+            consensus.suspected_faulty,
+            vec!["huge.accuracy", "huge.rtt"]
+        );
+    }
+}
@@ -2,6 +2,8 @@
 use std::fmt;
 use thiserror::Error;
 
+use crate::models::TimestampResponse;
+
 /// Errors that can occur in the Authentic Time Service
 #[derive(Error, Debug)]
 pub enum TimeServiceError {
@@ -21,14 +23,70 @@ pub enum TimeServiceError {
     #[error("Authority not found: {0}")]
     AuthorityNotFound(String),
 
+    /// Error when a timestamp names a signing key we do not know
+    #[error("Unknown authority key: {0}")]
+    UnknownKey(String),
+
+    /// Error when a timestamp's time falls outside its signing key's validity window
+    #[error("Timestamp outside validity window of key {0}")]
+    KeyNotValidAt(String),
+
+    /// Error when a Merkle proof is malformed
+    #[error("Invalid proof: {0}")]
+    InvalidProof(String),
+
+    /// Error when a Roughtime message is malformed
+    #[error("Roughtime error: {0}")]
+    RoughtimeError(String),
+
+    /// Error when too few authorities returned valid timestamps
+    #[error("Quorum not reached: {obtained} of {required} authorities")]
+    QuorumNotReached { required: usize, obtained: usize },
+
+    /// Error when a response answers no request we sent
+    #[error("Unsolicited response for nonce {0}")]
+    UnsolicitedResponse(String),
+
+    /// Error when a request is answered more than once
+    #[error("Duplicate response for nonce {0}")]
+    DuplicateResponse(String),
+
+    /// Error when a response does not match the request it answers
+    #[error("Response does not match request: {0}")]
+    ResponseMismatch(String),
+
+    /// Error when an authority returns a signed refusal
+    #[error("Request refused by authority: {:?}", .0.status)]
+    Refused(Box<TimestampResponse>),
+
+    /// Error when an authority refuses a request over quota; such refusals are unsigned
+    #[error("Rate limit exceeded (retry after {0:?} seconds)")]
+    RateLimitExceeded(Option<u64>),
+
     /// Error when timestamp request is rejected
     #[error("Timestamp request rejected: {0}")]
     RequestRejected(String),
 
+    /// Error when a payload uses a format version we do not understand
+    #[error("Unsupported payload version: {0}")]
+    UnsupportedVersion(u8),
+
+    /// Error when a message could not be delivered over the transport
+    #[error("Transport error: {0}")]
+    TransportError(String),
+
+    /// Error when a signing key cannot be loaded, saved or decrypted
+    #[error("Key error: {0}")]
+    KeyError(String),
+
     /// Error in serialization/deserialization
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    /// Error reading data to be timestamped
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
     /// Generic error with message
     #[error("{0}")]
     Generic(String),
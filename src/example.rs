@@ -1,7 +1,5 @@
 // Example application demonstrating the Authentic Time Service
-use authentic_time_service::{
-    AuthenticTimestamp, TimeAuthority, TimeAuthorityImpl, TimeServiceError, TspTimeService,
-};
+use authentic_time_service::{TspTimeService, Verdict, VerificationPolicy};
 use std::time::Duration;
 
 // Main function to demonstrate the Authentic Time Service
@@ -85,16 +83,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("   - Current timestamp: {}", timestamp.timestamp);
 
     // For demonstration, we'll consider timestamps valid for 5 minutes
-    let valid_duration = Duration::from_secs(300); // 5 minutes
-    let age = chrono::Utc::now()
-        .signed_duration_since(timestamp.timestamp)
-        .to_std()
-        .unwrap_or(Duration::from_secs(0));
-
-    if age > valid_duration {
-        println!("   - Timestamp is TOO OLD (age: {:?})! ❌", age);
-    } else {
-        println!("   - Timestamp is FRESH (age: {:?})! ✅", age);
+    verifier_service
+        .set_verification_policy(VerificationPolicy::new().with_max_age(Duration::from_secs(300)));
+
+    match verifier_service.evaluate_timestamp(&timestamp) {
+        Verdict::Valid => println!("   - Timestamp is FRESH! ✅"),
+        Verdict::Stale { age } => println!("   - Timestamp is TOO OLD (age: {:?})! ❌", age),
+        other => println!("   - Timestamp rejected: {:?} ❌", other),
     }
     println!();
 
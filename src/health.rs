@@ -0,0 +1,133 @@
+// Clock health: how far the authority's clock may be from true time
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Drift assumed for an undisciplined quartz oscillator, in parts per million
+pub const DEFAULT_MAX_DRIFT_PPM: u64 = 50;
+
+/// One comparison of the local clock against a reference
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ClockSync {
+    /// reference time minus local time at the moment of the measurement
+    pub offset: chrono::Duration,
+
+    /// how far the reference reading itself may be off (±)
+    pub uncertainty: Duration,
+
+    /// local time of the measurement
+    pub at: DateTime<Utc>,
+}
+
+impl ClockSync {
+    /// Error bound of the local clock right after this measurement
+    pub fn error_bound(&self) -> Duration {
+        self.offset
+            .abs()
+            .to_std()
+            .unwrap_or(Duration::MAX)
+            .saturating_add(self.uncertainty)
+    }
+}
+
+/// Synchronization state of a clock
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClockStatus {
+    /// never compared against a reference
+    Unsynchronized,
+
+    /// compared against a reference; the error is at most `error_bound`
+    Synchronized { error_bound: Duration },
+}
+
+/// Tracks the most recent synchronization and derives the current error bound.
+///
+/// After a measurement the error grows by the maximum drift rate until the
+/// next one, so an authority that loses its reference eventually exceeds any
+/// limit and stops signing.
+#[derive(Debug)]
+pub struct ClockHealth {
+    max_drift_ppm: u64,
+    last_sync: Mutex<Option<ClockSync>>,
+}
+
+impl Default for ClockHealth {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DRIFT_PPM)
+    }
+}
+
+impl ClockHealth {
+    /// Track a clock that drifts by at most `max_drift_ppm`
+    pub fn new(max_drift_ppm: u64) -> Self {
+        Self {
+            max_drift_ppm,
+            last_sync: Mutex::new(None),
+        }
+    }
+
+    /// Record a comparison against a reference clock or peer authorities
+    pub fn record_sync(&self, sync: ClockSync) {
+        *self.last_sync.lock().unwrap() = Some(sync);
+    }
+
+    /// Most recent synchronization, if any
+    pub fn last_sync(&self) -> Option<ClockSync> {
+        self.last_sync.lock().unwrap().clone()
+    }
+
+    /// Estimated error bound of the clock at `now`, or None if it was never synchronized
+    pub fn error_bound(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let sync = self.last_sync()?;
+        let elapsed = (now - sync.at).abs().to_std().unwrap_or(Duration::MAX);
+        let drift_nanos = elapsed
+            .as_nanos()
+            .saturating_mul(u128::from(self.max_drift_ppm))
+            / 1_000_000;
+        let drift = Duration::from_nanos(drift_nanos.min(u128::from(u64::MAX)) as u64);
+
+        Some(sync.error_bound().saturating_add(drift))
+    }
+
+    /// Synchronization state at `now`
+    pub fn status(&self, now: DateTime<Utc>) -> ClockStatus {
+        match self.error_bound(now) {
+            Some(error_bound) => ClockStatus::Synchronized { error_bound },
+            None => ClockStatus::Unsynchronized,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_error_bound_grows_with_drift() {
+        let health = ClockHealth::new(100);
+        let at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        assert_eq!(health.status(at), ClockStatus::Unsynchronized);
+
+        health.record_sync(ClockSync {
+            offset: chrono::Duration::milliseconds(-3),
+            uncertainty: Duration::from_millis(2),
+            at,
+        });
+        assert_eq!(health.error_bound(at), Some(Duration::from_millis(5)));
+
+        // 100 ppm over 1000 s adds 100 ms
+        let later = at + chrono::Duration::seconds(1000);
+        assert_eq!(health.error_bound(later), Some(Duration::from_millis(105)));
+
+        // a recorded uncertainty too large to add to saturates instead of panicking
+        health.record_sync(ClockSync {
+            offset: chrono::Duration::seconds(1),
+            uncertainty: Duration::MAX,
+            at,
+        });
+        assert_eq!(health.error_bound(later), Some(Duration::MAX));
+    }
+}
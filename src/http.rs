@@ -0,0 +1,415 @@
+// Minimal HTTP/JSON binding for serving and reaching time authorities
+
+use async_trait::async_trait;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::error::TimeServiceError;
+use crate::service::{
+    RequestEnvelope, ResponseEnvelope, TspCommunication, TspTimeService, METHODS,
+};
+
+/// Largest request or response body accepted, in bytes
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Largest header block accepted, in bytes
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+/// How long a client has to send its complete request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept, so running out of file descriptors doesn't spin
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serve a time service over HTTP.
+///
+/// Every method understood by `TspTimeService::handle_envelope` is exposed
+/// at `/<method>`, e.g. `POST /time/request` or `GET /time/metadata`.
+/// Connections that can't be accepted (e.g. when out of file descriptors)
+/// are logged and the server keeps accepting.
+pub async fn serve(listener: TcpListener, service: Arc<TspTimeService>) -> io::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("HTTP accept error: {}", e);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let service = service.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, service, REQUEST_TIMEOUT).await {
+                eprintln!("HTTP connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Handle a single request on a connection, then close it.
+///
+/// Clients that don't send their whole request within `timeout` are
+/// disconnected, so slow senders can't hold connections open.
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    service: Arc<TspTimeService>,
+    timeout: Duration,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let (path, body) = match tokio::time::timeout(timeout, read_request(&mut reader)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            let body = error_body(&e.to_string());
+            return write_response(reader.get_mut(), 400, &body).await;
+        }
+        Err(_) => {
+            let body = error_body("request timed out");
+            return write_response(reader.get_mut(), 408, &body).await;
+        }
+    };
+
+    // only known routes are dispatched; any query string is ignored
+    let route = path.split('?').next().unwrap_or_default();
+    let method = match METHODS
+        .iter()
+        .find(|method| route.strip_prefix('/') == Some(**method))
+    {
+        Some(method) => method.to_string(),
+        None => {
+            let body = error_body(&format!("unknown route {}", route));
+            return write_response(reader.get_mut(), 404, &body).await;
+        }
+    };
+
+    let envelope = RequestEnvelope {
+        method,
+        payload: body,
+        source: Some(peer.ip().to_string()),
+    };
+    let response = service.handle_envelope(envelope).await;
+
+    match response.error {
+        None => write_response(reader.get_mut(), 200, &response.payload).await,
+        Some(error) => write_response(reader.get_mut(), 422, &error_body(&error)).await,
+    }
+}
+
+/// Read the request line, headers and body of an HTTP request
+async fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<(String, Vec<u8>)> {
+    let request_line = read_line_bounded(reader, MAX_HEADER_SIZE).await?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts
+        .next()
+        .ok_or_else(|| invalid_data("malformed request line"))?
+        .to_string();
+    if method != "GET" && method != "POST" {
+        return Err(invalid_data(format!("unsupported method {}", method)));
+    }
+
+    let content_length = read_headers(reader).await?;
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok((path, body))
+}
+
+/// Consume headers up to the blank line and return the declared content length
+async fn read_headers<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> io::Result<usize> {
+    let mut content_length = 0;
+    let mut header_bytes = 0;
+
+    loop {
+        let line = read_line_bounded(reader, MAX_HEADER_SIZE - header_bytes).await?;
+        header_bytes += line.len();
+
+        if line.is_empty() {
+            return Err(invalid_data("truncated or oversized headers"));
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_data("invalid content-length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(invalid_data("body too large"));
+    }
+
+    Ok(content_length)
+}
+
+/// Read a line of at most `limit` bytes, failing if it is longer
+async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    limit: usize,
+) -> io::Result<String> {
+    let mut line = String::new();
+    (&mut *reader)
+        .take(limit as u64)
+        .read_line(&mut line)
+        .await?;
+    if line.len() == limit && !line.ends_with('\n') {
+        return Err(invalid_data("line too long"));
+    }
+    Ok(line)
+}
+
+async fn write_response(stream: &mut TcpStream, status: u16, body: &[u8]) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        _ => "Unprocessable Entity",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+fn error_body(message: &str) -> Vec<u8> {
+    serde_json::to_vec(&serde_json::json!({ "error": message })).unwrap_or_default()
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// `TspCommunication` implementation that talks to authorities served by `serve`
+pub struct HttpTransport {
+    /// how long to wait for a complete response
+    timeout: Duration,
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpTransport {
+    /// Create a new HTTP transport with a 10 second timeout
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Set how long to wait for a complete response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn post(&self, endpoint: &str, request: RequestEnvelope) -> io::Result<ResponseEnvelope> {
+        let (host, prefix) = parse_endpoint(endpoint)?;
+        let mut stream = TcpStream::connect(host).await?;
+
+        let head = format!(
+            "POST {}/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            prefix,
+            request.method,
+            host,
+            request.payload.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&request.payload).await?;
+
+        let mut reader = BufReader::new(stream);
+        let status_line = read_line_bounded(&mut reader, MAX_HEADER_SIZE).await?;
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| invalid_data("malformed status line"))?;
+
+        let content_length = read_headers(&mut reader).await?;
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).await?;
+
+        if status == 200 {
+            return Ok(ResponseEnvelope {
+                payload: body,
+                error: None,
+            });
+        }
+
+        let message = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+            .unwrap_or_else(|| format!("HTTP status {}", status));
+        Ok(ResponseEnvelope::error(message))
+    }
+}
+
+/// Split `http://host:port/prefix` into `host:port` and `/prefix`
+fn parse_endpoint(endpoint: &str) -> io::Result<(&str, &str)> {
+    let rest = endpoint
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid_data(format!("unsupported endpoint {}", endpoint)))?;
+
+    Ok(match rest.find('/') {
+        Some(idx) => (&rest[..idx], rest[idx..].trim_end_matches('/')),
+        None => (rest, ""),
+    })
+}
+
+#[async_trait]
+impl TspCommunication for HttpTransport {
+    async fn send_envelope(
+        &self,
+        endpoint: &str,
+        request: RequestEnvelope,
+    ) -> Result<ResponseEnvelope, TimeServiceError> {
+        match tokio::time::timeout(self.timeout, self.post(endpoint, request)).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(e)) => Err(TimeServiceError::TransportError(e.to_string())),
+            Err(_) => Err(TimeServiceError::TransportError(format!(
+                "request to {} timed out",
+                endpoint
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AuthorityMetadata;
+    use crate::service::METHOD_METADATA;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("http://127.0.0.1:8080").unwrap(),
+            ("127.0.0.1:8080", "")
+        );
+        assert_eq!(
+            parse_endpoint("http://time.example:80/api/").unwrap(),
+            ("time.example:80", "/api")
+        );
+        assert!(parse_endpoint("https://time.example").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_timestamp_over_http() {
+        let mut authority = TspTimeService::new();
+        authority.as_authority("http.authority".to_string());
+        let public_key = authority.get_authority_public_key().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener, Arc::new(authority)));
+
+        let mut client = TspTimeService::new();
+        client.set_transport(Box::new(HttpTransport::new()));
+        client.add_authority_endpoint("http.authority".to_string(), endpoint.clone());
+        client
+            .add_authority_key("http.authority".to_string(), &public_key)
+            .unwrap();
+
+        let timestamp = client.request_timestamp("http.authority").await.unwrap();
+        assert!(client.verify_timestamp(&timestamp).unwrap());
+
+        let transport: &dyn TspCommunication = &HttpTransport::new();
+        let metadata: AuthorityMetadata = transport
+            .send_request(&endpoint, METHOD_METADATA, &())
+            .await
+            .unwrap();
+        assert_eq!(metadata.public_key, public_key);
+
+        // Unknown methods come back as remote errors
+        let result: Result<AuthorityMetadata, _> =
+            transport.send_request(&endpoint, "time/unknown", &()).await;
+        assert!(matches!(result, Err(TimeServiceError::RequestRejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_slow_clients_and_unknown_routes_are_turned_away() {
+        let mut authority = TspTimeService::new();
+        authority.as_authority("http.authority".to_string());
+        let service = Arc::new(authority);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // a client that never finishes its headers is answered and disconnected
+        let mut slow = TcpStream::connect(addr).await.unwrap();
+        let (stream, peer) = listener.accept().await.unwrap();
+        let server = tokio::spawn(handle_connection(
+            stream,
+            peer,
+            service.clone(),
+            Duration::from_millis(50),
+        ));
+        slow.write_all(b"POST /time/request HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        slow.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 408"));
+        server.await.unwrap().unwrap();
+
+        // lines are read with a bound, so an endless request line is refused
+        // without buffering it
+        let mut endless = TcpStream::connect(addr).await.unwrap();
+        let (stream, peer) = listener.accept().await.unwrap();
+        let server = tokio::spawn(handle_connection(
+            stream,
+            peer,
+            service.clone(),
+            REQUEST_TIMEOUT,
+        ));
+        endless
+            .write_all(&vec![b'A'; MAX_HEADER_SIZE])
+            .await
+            .unwrap();
+        let mut response = String::new();
+        endless.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        server.await.unwrap().unwrap();
+
+        // the query string is stripped, and unknown paths are not dispatched
+        tokio::spawn(serve(listener, service));
+        for (path, status) in [
+            ("/time/metadata?x=1", "200"),
+            ("/time/metadata/../request", "404"),
+        ] {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {} HTTP/1.1\r\n\r\n", path);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {}", status)),
+                "{}",
+                path
+            );
+        }
+    }
+}
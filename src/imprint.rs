@@ -0,0 +1,131 @@
+// Message imprints: hashes of the data being timestamped
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Hash algorithms supported for message imprints
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// SHA-256 (32-byte digest)
+    Sha256,
+
+    /// SHA-512 (64-byte digest)
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Stable identifier used in signed payloads
+    pub fn id(&self) -> u64 {
+        match self {
+            HashAlgorithm::Sha256 => 1,
+            HashAlgorithm::Sha512 => 2,
+        }
+    }
+
+    /// Length in bytes of digests produced by this algorithm
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    /// Hash a byte slice
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    /// Hash everything produced by a reader
+    pub fn digest_reader<R: Read>(&self, reader: R) -> io::Result<Vec<u8>> {
+        match self {
+            HashAlgorithm::Sha256 => digest_stream::<Sha256, R>(reader),
+            HashAlgorithm::Sha512 => digest_stream::<Sha512, R>(reader),
+        }
+    }
+}
+
+/// hash of the data a timestamp vouches for, together with the algorithm used
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageImprint {
+    /// algorithm used to compute the digest
+    pub hash_algorithm: HashAlgorithm,
+
+    /// digest of the timestamped data
+    pub hashed_message: Vec<u8>,
+}
+
+impl MessageImprint {
+    /// Create an imprint from a precomputed digest
+    pub fn new(hash_algorithm: HashAlgorithm, hashed_message: Vec<u8>) -> Self {
+        Self {
+            hash_algorithm,
+            hashed_message,
+        }
+    }
+
+    /// Compute the imprint of a byte slice
+    pub fn from_data(hash_algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        Self::new(hash_algorithm, hash_algorithm.digest(data))
+    }
+
+    /// Compute the imprint of everything produced by a reader
+    pub fn from_reader<R: Read>(hash_algorithm: HashAlgorithm, reader: R) -> io::Result<Self> {
+        Ok(Self::new(
+            hash_algorithm,
+            hash_algorithm.digest_reader(reader)?,
+        ))
+    }
+
+    /// Compute the imprint of a file's contents
+    pub fn from_file<P: AsRef<Path>>(hash_algorithm: HashAlgorithm, path: P) -> io::Result<Self> {
+        Self::from_reader(hash_algorithm, File::open(path)?)
+    }
+
+    /// Check that the digest has the length its algorithm produces
+    pub fn is_well_formed(&self) -> bool {
+        self.hashed_message.len() == self.hash_algorithm.digest_len()
+    }
+
+    /// Check whether this imprint was computed over the given data
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.hash_algorithm.digest(data) == self.hashed_message
+    }
+}
+
+fn digest_stream<D: Digest, R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    let mut buf = [0u8; 8192];
+
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_and_slice_imprints_agree() {
+        let data = vec![7u8; 20_000];
+        let from_slice = MessageImprint::from_data(HashAlgorithm::Sha512, &data);
+        let from_reader = MessageImprint::from_reader(HashAlgorithm::Sha512, &data[..]).unwrap();
+
+        assert_eq!(from_slice, from_reader);
+        assert!(from_slice.is_well_formed());
+        assert!(from_slice.matches(&data));
+        assert!(!from_slice.matches(b"other data"));
+    }
+}
@@ -0,0 +1,106 @@
+// Append-only JSON-lines files that survive crashes mid-write
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::error::TimeServiceError;
+
+/// Open (or create) a journal, passing each record to `each` and returning
+/// the file opened for appending.
+///
+/// Records are streamed, so large journals are never held in memory at once.
+/// A crash can leave a torn final line without its newline; it is cut off so
+/// the next record starts cleanly. Everything before it is intact, so a line
+/// that doesn't parse anywhere else means the journal is corrupt, and opening
+/// it fails rather than silently losing records.
+pub fn open<T, F>(path: &Path, mut each: F) -> Result<File, TimeServiceError>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), TimeServiceError>,
+{
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+
+    let mut reader = BufReader::new(&file);
+    let mut line = Vec::new();
+    let mut offset = 0u64;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        if line.last() != Some(&b'\n') {
+            file.set_len(offset)?;
+            break;
+        }
+
+        let record = serde_json::from_slice(&line[..read - 1]).map_err(|e| {
+            TimeServiceError::generic(format!(
+                "corrupt journal {} at byte {}: {}",
+                path.display(),
+                offset,
+                e
+            ))
+        })?;
+        each(record)?;
+        offset += read as u64;
+    }
+    Ok(file)
+}
+
+/// Append one record as a line, to the file or to a buffer bound for it;
+/// callers sync the file before relying on it
+pub fn append<T: Serialize, W: Write>(out: &mut W, record: &T) -> Result<(), TimeServiceError> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    out.write_all(&line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> Result<(Vec<u64>, File), TimeServiceError> {
+        let mut records = Vec::new();
+        let file = open(path, |record: u64| {
+            records.push(record);
+            Ok(())
+        })?;
+        Ok((records, file))
+    }
+
+    #[test]
+    fn test_torn_last_line_is_cut_off() {
+        let path = std::env::temp_dir().join(format!("journal-{:x}.jsonl", rand::random::<u64>()));
+        std::fs::write(&path, b"1\n2\n3").unwrap();
+
+        // an unterminated line is torn even if what is left of it parses
+        let (records, mut file) = read(&path).unwrap();
+        assert_eq!(records, vec![1, 2]);
+        append(&mut file, &4u64).unwrap();
+        drop(file);
+
+        let (records, _) = read(&path).unwrap();
+        assert_eq!(records, vec![1, 2, 4]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_complete_line_is_an_error() {
+        let path = std::env::temp_dir().join(format!("journal-{:x}.jsonl", rand::random::<u64>()));
+        std::fs::write(&path, b"1\n{\"corrupt\n3\n").unwrap();
+
+        assert!(read(&path).is_err());
+        // nothing was cut off
+        assert_eq!(std::fs::read(&path).unwrap(), b"1\n{\"corrupt\n3\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
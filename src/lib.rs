@@ -2,16 +2,55 @@
 
 // Module declarations
 pub mod authority;
+pub mod batch;
+pub mod canonical;
+pub mod chain;
 pub mod client;
+pub mod clock;
+pub mod consensus;
 pub mod error;
+pub mod health;
+pub mod http;
+pub mod imprint;
+pub mod journal;
+pub mod keys;
+pub mod loopback;
+pub mod merkle;
 pub mod models;
+pub mod monitor;
+pub mod nonce;
+pub mod pending;
+pub mod policy;
+pub mod quorum;
+pub mod ratelimit;
+pub mod roughtime;
+pub mod serial;
 pub mod service;
 
 // Re-exports for convenient access
-pub use models::{AuthenticTimestamp, TimestampRequest, TimestampResponse, TimestampStatus};
+pub use models::{
+    Accuracy, AuthenticTimestamp, AuthorityMetadata, SignedTreeHead, TimestampRequest,
+    TimestampResponse, TimestampStatus,
+};
 
 pub use authority::{TimeAuthority, TimeAuthorityImpl};
+pub use batch::BatchConfig;
 
+pub use chain::{LogRange, TimestampLog};
 pub use client::TimeClient;
+pub use clock::{ClockSource, ManualClock, OffsetClock, SystemClock};
 pub use error::TimeServiceError;
-pub use service::TspTimeService;
+pub use health::{ClockHealth, ClockStatus, ClockSync};
+pub use imprint::{HashAlgorithm, MessageImprint};
+pub use keys::KeyFormat;
+pub use merkle::{ConsistencyProof, InclusionProof};
+pub use monitor::{Misbehavior, MisbehaviorProof, Monitor};
+pub use nonce::{FileNonceStore, MemoryNonceStore, NonceStore};
+pub use policy::{Verdict, VerificationPolicy};
+pub use quorum::QuorumTimestamp;
+pub use ratelimit::{RateLimit, RateLimitConfig};
+pub use roughtime::{ChainResponse, ChainedEvidence, RoughtimeResponse, RoughtimeServer};
+pub use service::{
+    AuthorityConfig, KeyFileConfig, RequestEnvelope, ResponseEnvelope, TspCommunication,
+    TspTimeService,
+};
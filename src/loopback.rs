@@ -0,0 +1,335 @@
+// In-memory loopback transport for running several services in one process
+
+use async_trait::async_trait;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::TimeServiceError;
+use crate::service::{RequestEnvelope, ResponseEnvelope, TspCommunication, TspTimeService};
+
+/// Simulated network conditions applied to every message on a loopback network
+#[derive(Clone, Debug)]
+pub struct NetworkConditions {
+    /// fixed one-way delay applied to every message
+    pub latency: Duration,
+
+    /// maximum random extra delay per message; non-zero jitter reorders messages
+    pub jitter: Duration,
+
+    /// probability (0.0 - 1.0) that any single message is dropped
+    pub loss_rate: f64,
+
+    /// how long a sender waits before reporting a dropped message as lost
+    pub timeout: Duration,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            loss_rate: 0.0,
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Counters for messages carried by a loopback network
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkStats {
+    /// messages delivered to their destination
+    pub delivered: u64,
+
+    /// messages dropped by simulated packet loss
+    pub dropped: u64,
+}
+
+struct NetworkInner {
+    /// services reachable on this network (endpoint -> service)
+    services: Mutex<HashMap<String, Arc<TspTimeService>>>,
+
+    /// authorities spawned by the harness (ID -> public key)
+    authorities: Mutex<Vec<(String, Vec<u8>)>>,
+
+    /// current network conditions
+    conditions: Mutex<NetworkConditions>,
+
+    /// message counters
+    stats: Mutex<NetworkStats>,
+}
+
+/// An in-process network connecting several `TspTimeService` instances
+#[derive(Clone)]
+pub struct LoopbackNetwork {
+    inner: Arc<NetworkInner>,
+}
+
+impl Default for LoopbackNetwork {
+    fn default() -> Self {
+        Self::new(NetworkConditions::default())
+    }
+}
+
+impl LoopbackNetwork {
+    /// Create a new loopback network with the given conditions
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self {
+            inner: Arc::new(NetworkInner {
+                services: Mutex::new(HashMap::new()),
+                authorities: Mutex::new(Vec::new()),
+                conditions: Mutex::new(conditions),
+                stats: Mutex::new(NetworkStats::default()),
+            }),
+        }
+    }
+
+    /// Endpoint under which the harness registers an authority
+    pub fn endpoint_for(authority_id: &str) -> String {
+        format!("loopback://{}", authority_id)
+    }
+
+    /// Change the conditions applied to subsequent messages
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        *self.inner.conditions.lock().unwrap() = conditions;
+    }
+
+    /// Get the message counters
+    pub fn stats(&self) -> NetworkStats {
+        self.inner.stats.lock().unwrap().clone()
+    }
+
+    /// Create a transport that sends over this network
+    pub fn transport(&self) -> Box<dyn TspCommunication> {
+        Box::new(LoopbackTransport {
+            network: self.clone(),
+        })
+    }
+
+    /// Make a service reachable at the given endpoint
+    pub fn register(&self, endpoint: String, service: Arc<TspTimeService>) {
+        self.inner
+            .services
+            .lock()
+            .unwrap()
+            .insert(endpoint, service);
+    }
+
+    /// Create an authority service and make it reachable on this network
+    pub fn spawn_authority(&self, authority_id: &str) -> Arc<TspTimeService> {
+        let mut service = TspTimeService::new();
+        service.as_authority(authority_id.to_string());
+        service.set_transport(self.transport());
+
+        let public_key = service
+            .get_authority_public_key()
+            .expect("authority service has a public key");
+        self.inner
+            .authorities
+            .lock()
+            .unwrap()
+            .push((authority_id.to_string(), public_key));
+
+        let service = Arc::new(service);
+        self.register(Self::endpoint_for(authority_id), service.clone());
+        service
+    }
+
+    /// Create a client service that knows every authority spawned so far
+    pub fn spawn_client(
+        &self,
+        client_id: Option<&str>,
+    ) -> Result<TspTimeService, TimeServiceError> {
+        let mut service = TspTimeService::new();
+        if let Some(id) = client_id {
+            service.as_authenticated_client(id.to_string());
+        }
+        service.set_transport(self.transport());
+
+        for (authority_id, public_key) in self.inner.authorities.lock().unwrap().iter() {
+            service.add_authority_endpoint(authority_id.clone(), Self::endpoint_for(authority_id));
+            service.add_authority_key(authority_id.clone(), public_key)?;
+        }
+
+        Ok(service)
+    }
+
+    /// Sleep for one simulated hop; returns false if the message was dropped
+    async fn hop(&self) -> bool {
+        let (delay, dropped, timeout) = {
+            let conditions = self.inner.conditions.lock().unwrap();
+            let mut rng = rand::thread_rng();
+
+            let jitter = conditions.jitter.as_micros() as u64;
+            let extra = if jitter > 0 {
+                Duration::from_micros(rng.gen_range(0, jitter + 1))
+            } else {
+                Duration::from_micros(0)
+            };
+            let dropped = rng.gen::<f64>() < conditions.loss_rate;
+
+            (conditions.latency + extra, dropped, conditions.timeout)
+        };
+
+        if dropped {
+            self.inner.stats.lock().unwrap().dropped += 1;
+            tokio::time::sleep(timeout).await;
+            return false;
+        }
+
+        tokio::time::sleep(delay).await;
+        self.inner.stats.lock().unwrap().delivered += 1;
+        true
+    }
+}
+
+/// `TspCommunication` implementation backed by a `LoopbackNetwork`
+pub struct LoopbackTransport {
+    network: LoopbackNetwork,
+}
+
+#[async_trait]
+impl TspCommunication for LoopbackTransport {
+    async fn send_envelope(
+        &self,
+        endpoint: &str,
+        request: RequestEnvelope,
+    ) -> Result<ResponseEnvelope, TimeServiceError> {
+        // request leg
+        if !self.network.hop().await {
+            return Err(TimeServiceError::TransportError(format!(
+                "request to {} lost",
+                endpoint
+            )));
+        }
+
+        let service = self
+            .network
+            .inner
+            .services
+            .lock()
+            .unwrap()
+            .get(endpoint)
+            .cloned()
+            .ok_or_else(|| {
+                TimeServiceError::TransportError(format!("no service at {}", endpoint))
+            })?;
+
+        let response = service.handle_envelope(request).await;
+
+        // response leg
+        if !self.network.hop().await {
+            return Err(TimeServiceError::TransportError(format!(
+                "response from {} lost",
+                endpoint
+            )));
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roughtime::{self, ChainResponse};
+
+    #[tokio::test]
+    async fn test_multiple_authorities_over_loopback() {
+        let network = LoopbackNetwork::new(NetworkConditions {
+            latency: Duration::from_millis(2),
+            jitter: Duration::from_millis(5),
+            ..NetworkConditions::default()
+        });
+        network.spawn_authority("authority.a");
+        network.spawn_authority("authority.b");
+
+        let client = Arc::new(network.spawn_client(Some("client.example")).unwrap());
+        let verifier = network.spawn_client(None).unwrap();
+
+        // Fire concurrent requests at both authorities; jitter reorders them
+        let mut handles = Vec::new();
+        for i in 0..10 {
+            let client = client.clone();
+            let authority = if i % 2 == 0 {
+                "authority.a"
+            } else {
+                "authority.b"
+            };
+            handles.push(tokio::spawn(async move {
+                client.request_timestamp(authority).await
+            }));
+        }
+
+        for handle in handles {
+            let timestamp = handle.await.unwrap().unwrap();
+            assert!(verifier.verify_timestamp(&timestamp).unwrap());
+        }
+
+        assert_eq!(network.stats().delivered, 20);
+    }
+
+    #[tokio::test]
+    async fn test_packet_loss_surfaces_as_transport_error() {
+        let network = LoopbackNetwork::new(NetworkConditions {
+            loss_rate: 1.0,
+            timeout: Duration::from_millis(1),
+            ..NetworkConditions::default()
+        });
+        network.spawn_authority("authority.a");
+        let client = network.spawn_client(None).unwrap();
+
+        let result = client.request_timestamp("authority.a").await;
+        assert!(matches!(result, Err(TimeServiceError::TransportError(_))));
+        assert_eq!(network.stats().dropped, 1);
+
+        // Restoring the link restores service
+        network.set_conditions(NetworkConditions::default());
+        assert!(client.request_timestamp("authority.a").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_chained_timestamps_verify_offline_in_order() {
+        let network = LoopbackNetwork::default();
+        let authorities: Vec<String> = ["authority.a", "authority.b", "authority.c"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        for id in &authorities {
+            network.spawn_authority(id);
+        }
+        let client = network.spawn_client(None).unwrap();
+
+        let evidence = client.request_timestamp_chain(&authorities).await.unwrap();
+
+        // a third party re-verifies the bundle without contacting anyone
+        let json = serde_json::to_string(&evidence).unwrap();
+        let verifier = network.spawn_client(None).unwrap();
+        let timestamps = verifier
+            .verify_timestamp_chain(&serde_json::from_str(&json).unwrap())
+            .unwrap();
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(timestamps[2].authority_id, "authority.c");
+        assert_eq!(roughtime::find_timestamp_inconsistency(&timestamps), None);
+
+        // presenting C's timestamp as coming before B's breaks the chain
+        let mut reordered = evidence.clone();
+        reordered.links.swap(1, 2);
+        assert!(verifier.verify_timestamp_chain(&reordered).is_err());
+
+        // so does swapping in a timestamp that wasn't asked for with the chained nonce
+        let mut substituted = evidence.clone();
+        let unchained = client.request_timestamp("authority.b").await.unwrap();
+        substituted.links[1].response = ChainResponse::Timestamp(Box::new(unchained));
+        assert!(verifier.verify_timestamp_chain(&substituted).is_err());
+
+        // a timestamp earlier than one issued before it is reported
+        let mut skewed = timestamps.clone();
+        skewed[1].timestamp = skewed[0].timestamp - chrono::Duration::seconds(10);
+        assert_eq!(
+            roughtime::find_timestamp_inconsistency(&skewed),
+            Some((0, 1))
+        );
+    }
+}
@@ -0,0 +1,561 @@
+// Merkle tree hashing, inclusion and consistency proofs (RFC 6962 / RFC 9162 construction)
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::TimeServiceError;
+
+/// Prefix distinguishing leaf hashes from interior node hashes
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix for interior node hashes
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hash of a leaf's data
+pub fn leaf_hash(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+/// Hash of an interior node from its children's hashes
+pub fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// Storage for the nodes of a `MerkleLog`, addressed by height and position
+pub trait NodeStore: Send {
+    /// Append a node at `height`
+    fn push(&mut self, height: usize, hash: Vec<u8>) -> Result<(), TimeServiceError>;
+
+    /// The node at `index` of `height`
+    fn node(&self, height: usize, index: u64) -> Result<Vec<u8>, TimeServiceError>;
+
+    /// Number of nodes stored at `height`
+    fn count(&self, height: usize) -> u64;
+}
+
+impl<S: NodeStore + ?Sized> NodeStore for Box<S> {
+    fn push(&mut self, height: usize, hash: Vec<u8>) -> Result<(), TimeServiceError> {
+        (**self).push(height, hash)
+    }
+
+    fn node(&self, height: usize, index: u64) -> Result<Vec<u8>, TimeServiceError> {
+        (**self).node(height, index)
+    }
+
+    fn count(&self, height: usize) -> u64 {
+        (**self).count(height)
+    }
+}
+
+/// Nodes kept in memory
+#[derive(Default)]
+pub struct MemoryNodes {
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl NodeStore for MemoryNodes {
+    fn push(&mut self, height: usize, hash: Vec<u8>) -> Result<(), TimeServiceError> {
+        if self.levels.len() <= height {
+            self.levels.resize_with(height + 1, Vec::new);
+        }
+        self.levels[height].push(hash);
+        Ok(())
+    }
+
+    fn node(&self, height: usize, index: u64) -> Result<Vec<u8>, TimeServiceError> {
+        self.levels
+            .get(height)
+            .and_then(|level| level.get(index as usize))
+            .cloned()
+            .ok_or_else(|| TimeServiceError::InvalidProof("missing tree node".to_string()))
+    }
+
+    fn count(&self, height: usize) -> u64 {
+        self.levels
+            .get(height)
+            .map_or(0, |level| level.len() as u64)
+    }
+}
+
+/// Size of a SHA-256 node hash
+const NODE_SIZE: u64 = 32;
+
+/// Nodes kept on disk, one file of fixed-size hashes per height, read on demand
+pub struct FileNodes {
+    dir: PathBuf,
+    levels: Vec<(File, u64)>,
+}
+
+impl FileNodes {
+    /// Start an empty store in `dir`, discarding any nodes already there
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<Self, TimeServiceError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "nodes") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Self {
+            dir,
+            levels: Vec::new(),
+        })
+    }
+}
+
+impl NodeStore for FileNodes {
+    fn push(&mut self, height: usize, hash: Vec<u8>) -> Result<(), TimeServiceError> {
+        if hash.len() as u64 != NODE_SIZE {
+            return Err(TimeServiceError::InvalidProof(
+                "node hash has the wrong size".to_string(),
+            ));
+        }
+        while self.levels.len() <= height {
+            let path = self.dir.join(format!("{}.nodes", self.levels.len()));
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .read(true)
+                .write(true)
+                .open(path)?;
+            self.levels.push((file, 0));
+        }
+        // the tree is rebuilt from the log on open, so nodes need no sync
+        let (file, count) = &mut self.levels[height];
+        file.seek(SeekFrom::Start(*count * NODE_SIZE))?;
+        file.write_all(&hash)?;
+        *count += 1;
+        Ok(())
+    }
+
+    fn node(&self, height: usize, index: u64) -> Result<Vec<u8>, TimeServiceError> {
+        let mut file = match self.levels.get(height) {
+            Some((file, count)) if index < *count => file,
+            _ => {
+                return Err(TimeServiceError::InvalidProof(
+                    "missing tree node".to_string(),
+                ))
+            }
+        };
+        let mut hash = vec![0; NODE_SIZE as usize];
+        file.seek(SeekFrom::Start(index * NODE_SIZE))?;
+        file.read_exact(&mut hash)?;
+        Ok(hash)
+    }
+
+    fn count(&self, height: usize) -> u64 {
+        self.levels.get(height).map_or(0, |(_, count)| *count)
+    }
+}
+
+/// An append-only Merkle log that can prove inclusion and consistency for
+/// any earlier tree size, as in RFC 6962.
+///
+/// Only hashes of complete (power-of-two) subtrees are stored, so the root
+/// and proofs for any size are assembled from O(log² n) stored nodes. Nodes
+/// live in memory by default, or on disk with `FileNodes`.
+pub struct MerkleLog<S: NodeStore = MemoryNodes> {
+    /// node `(h, i)` is the root of the complete subtree of 2^h leaves starting at leaf i·2^h
+    store: S,
+}
+
+impl Default for MerkleLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerkleLog {
+    /// Create an empty log in memory
+    pub fn new() -> Self {
+        Self::with_store(MemoryNodes::default())
+    }
+
+    /// Build a log over `leaves` (already hashed with `leaf_hash`)
+    pub fn from_leaves(leaves: Vec<Vec<u8>>) -> Self {
+        let mut log = Self::new();
+        for leaf in leaves {
+            // memory stores can't fail
+            let _ = log.append(leaf);
+        }
+        log
+    }
+}
+
+impl<S: NodeStore> MerkleLog<S> {
+    /// Create an empty log over an empty store
+    pub fn with_store(store: S) -> Self {
+        Self { store }
+    }
+
+    /// Number of leaves
+    pub fn len(&self) -> u64 {
+        self.store.count(0)
+    }
+
+    /// Whether the log has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append a leaf hash and return its index
+    pub fn append(&mut self, leaf_hash: Vec<u8>) -> Result<u64, TimeServiceError> {
+        let index = self.len();
+        let mut node = leaf_hash;
+        let mut position = index;
+        for height in 0.. {
+            // a left child waits for its sibling; a right child completes its parent
+            let sibling = match position & 1 {
+                1 => Some(self.store.node(height, position - 1)?),
+                _ => None,
+            };
+            self.store.push(height, node.clone())?;
+            let Some(sibling) = sibling else {
+                break;
+            };
+            node = node_hash(&sibling, &node);
+            position >>= 1;
+        }
+        Ok(index)
+    }
+
+    /// The leaf hash at `index`
+    pub fn leaf(&self, index: u64) -> Result<Vec<u8>, TimeServiceError> {
+        if index >= self.len() {
+            return Err(TimeServiceError::InvalidProof(format!(
+                "leaf {} is beyond the tree of {} entries",
+                index,
+                self.len()
+            )));
+        }
+        self.store.node(0, index)
+    }
+
+    /// Root hash of the tree formed by the first `size` leaves
+    pub fn root(&self, size: u64) -> Result<Vec<u8>, TimeServiceError> {
+        self.check_size(size)?;
+        match size {
+            0 => Ok(Sha256::digest(b"").to_vec()),
+            size => self.subtree_root(0, size),
+        }
+    }
+
+    /// Inclusion proof for the leaf at `index` in the tree of the first `size` leaves
+    pub fn inclusion_proof(
+        &self,
+        index: u64,
+        size: u64,
+    ) -> Result<InclusionProof, TimeServiceError> {
+        self.check_size(size)?;
+        if index >= size {
+            return Err(TimeServiceError::InvalidProof(
+                "leaf index outside the tree".to_string(),
+            ));
+        }
+
+        let mut path = Vec::new();
+        self.inclusion_path(index, 0, size, &mut path)?;
+        Ok(InclusionProof {
+            leaf_index: index,
+            tree_size: size,
+            path,
+        })
+    }
+
+    /// Proof that the tree of the first `first` leaves is a prefix of the tree of the first `second`
+    pub fn consistency_proof(
+        &self,
+        first: u64,
+        second: u64,
+    ) -> Result<ConsistencyProof, TimeServiceError> {
+        self.check_size(second)?;
+        if first > second {
+            return Err(TimeServiceError::InvalidProof(
+                "first tree is larger than the second".to_string(),
+            ));
+        }
+
+        let mut path = Vec::new();
+        if first > 0 && first < second {
+            self.consistency_path(first, 0, second, true, &mut path)?;
+        }
+        Ok(ConsistencyProof {
+            first_size: first,
+            second_size: second,
+            path,
+        })
+    }
+
+    fn check_size(&self, size: u64) -> Result<(), TimeServiceError> {
+        if size > self.len() {
+            return Err(TimeServiceError::InvalidProof(format!(
+                "tree size {} exceeds the log's {} entries",
+                size,
+                self.len()
+            )));
+        }
+        Ok(())
+    }
+
+    // root of the `size` leaves starting at `start`, following RFC 6962's split
+    fn subtree_root(&self, start: u64, size: u64) -> Result<Vec<u8>, TimeServiceError> {
+        if size.is_power_of_two() {
+            let height = size.trailing_zeros() as usize;
+            return self.store.node(height, start >> height);
+        }
+        let k = split_point(size);
+        Ok(node_hash(
+            &self.subtree_root(start, k)?,
+            &self.subtree_root(start + k, size - k)?,
+        ))
+    }
+
+    // RFC 6962 PATH(m, D[start:start+size]), bottom-up
+    fn inclusion_path(
+        &self,
+        index: u64,
+        start: u64,
+        size: u64,
+        path: &mut Vec<Vec<u8>>,
+    ) -> Result<(), TimeServiceError> {
+        if size <= 1 {
+            return Ok(());
+        }
+        let k = split_point(size);
+        if index < k {
+            self.inclusion_path(index, start, k, path)?;
+            path.push(self.subtree_root(start + k, size - k)?);
+        } else {
+            self.inclusion_path(index - k, start + k, size - k, path)?;
+            path.push(self.subtree_root(start, k)?);
+        }
+        Ok(())
+    }
+
+    // RFC 6962 SUBPROOF(m, D[start:start+size], whole)
+    fn consistency_path(
+        &self,
+        first: u64,
+        start: u64,
+        size: u64,
+        whole: bool,
+        path: &mut Vec<Vec<u8>>,
+    ) -> Result<(), TimeServiceError> {
+        if first == size {
+            if !whole {
+                path.push(self.subtree_root(start, size)?);
+            }
+            return Ok(());
+        }
+        let k = split_point(size);
+        if first <= k {
+            self.consistency_path(first, start, k, whole, path)?;
+            path.push(self.subtree_root(start + k, size - k)?);
+        } else {
+            self.consistency_path(first - k, start + k, size - k, false, path)?;
+            path.push(self.subtree_root(start, k)?);
+        }
+        Ok(())
+    }
+}
+
+/// Largest power of two strictly less than `n` (n > 1)
+fn split_point(n: u64) -> u64 {
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// Proof that one tree is an append-only extension of another
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConsistencyProof {
+    /// size of the earlier tree
+    pub first_size: u64,
+
+    /// size of the later tree
+    pub second_size: u64,
+
+    /// subtree hashes connecting the two roots
+    pub path: Vec<Vec<u8>>,
+}
+
+impl ConsistencyProof {
+    /// Check that the tree with root `first_root` is a prefix of the tree with root `second_root`
+    pub fn verify(&self, first_root: &[u8], second_root: &[u8]) -> Result<bool, TimeServiceError> {
+        let (first, second) = (self.first_size, self.second_size);
+        if first > second {
+            return Err(TimeServiceError::InvalidProof(
+                "first tree is larger than the second".to_string(),
+            ));
+        }
+        // an empty tree is a prefix of anything, and a tree only of itself
+        if first == 0 || first == second {
+            return Ok(self.path.is_empty() && (first == 0 || first_root == second_root));
+        }
+
+        // RFC 9162 section 2.1.4.2
+        let mut path = self.path.iter();
+        let seed = if first.is_power_of_two() {
+            first_root.to_vec()
+        } else {
+            match path.next() {
+                Some(seed) => seed.clone(),
+                None => return Ok(false),
+            }
+        };
+
+        let (mut index, mut last) = (first - 1, second - 1);
+        while index & 1 == 1 {
+            index >>= 1;
+            last >>= 1;
+        }
+        let (mut first_hash, mut second_hash) = (seed.clone(), seed);
+        for node in path {
+            if last == 0 {
+                return Ok(false);
+            }
+            if index & 1 == 1 || index == last {
+                first_hash = node_hash(node, &first_hash);
+                second_hash = node_hash(node, &second_hash);
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                second_hash = node_hash(&second_hash, node);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+
+        Ok(last == 0 && first_hash == first_root && second_hash == second_root)
+    }
+}
+
+/// Audit path proving that a leaf is part of a tree of a given size
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InclusionProof {
+    /// position of the leaf, starting at 0
+    pub leaf_index: u64,
+
+    /// number of leaves in the tree
+    pub tree_size: u64,
+
+    /// sibling hashes from the leaf up to the root
+    pub path: Vec<Vec<u8>>,
+}
+
+impl InclusionProof {
+    /// Root hash of the tree this proof places `leaf_hash` in
+    pub fn root_from(&self, leaf_hash: &[u8]) -> Result<Vec<u8>, TimeServiceError> {
+        if self.leaf_index >= self.tree_size {
+            return Err(TimeServiceError::InvalidProof(
+                "leaf index outside the tree".to_string(),
+            ));
+        }
+
+        let (mut index, mut last) = (self.leaf_index, self.tree_size - 1);
+        let mut hash = leaf_hash.to_vec();
+        for sibling in &self.path {
+            if last == 0 {
+                return Err(TimeServiceError::InvalidProof(
+                    "inclusion path too long".to_string(),
+                ));
+            }
+            if index & 1 == 1 || index == last {
+                hash = node_hash(sibling, &hash);
+                // skip levels where this node has no right sibling
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+
+        if last != 0 {
+            return Err(TimeServiceError::InvalidProof(
+                "inclusion path too short".to_string(),
+            ));
+        }
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6962 MTH, straight from the definition
+    fn reference_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+        match leaves.len() {
+            0 => Sha256::digest(b"").to_vec(),
+            1 => leaves[0].clone(),
+            n => {
+                let k = split_point(n as u64) as usize;
+                node_hash(&reference_root(&leaves[..k]), &reference_root(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_rfc6962_shape() {
+        // with three leaves the third is promoted: root = H(H(a, b), c)
+        let leaves: Vec<_> = [b"a", b"b", b"c"].iter().map(|d| leaf_hash(*d)).collect();
+        let expected = node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2]);
+        assert_eq!(MerkleLog::from_leaves(leaves).root(3).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_log_proves_inclusion_and_consistency_for_every_size() {
+        let dir = std::env::temp_dir().join(format!("merkle-{:x}", rand::random::<u64>()));
+        let mut memory = MerkleLog::new();
+        let mut disk = MerkleLog::with_store(FileNodes::create(&dir).unwrap());
+        let leaves: Vec<_> = (0..17u8).map(|i| leaf_hash(&[i])).collect();
+        for leaf in &leaves {
+            memory.append(leaf.clone()).unwrap();
+            disk.append(leaf.clone()).unwrap();
+        }
+        assert_eq!(disk.leaf(9).unwrap(), leaves[9]);
+        assert!(disk.leaf(17).is_err());
+
+        for second in 1..=memory.len() {
+            let second_root = memory.root(second).unwrap();
+            assert_eq!(second_root, reference_root(&leaves[..second as usize]));
+            assert_eq!(disk.root(second).unwrap(), second_root);
+
+            for index in 0..second {
+                let proof = memory.inclusion_proof(index, second).unwrap();
+                assert_eq!(disk.inclusion_proof(index, second).unwrap(), proof);
+                assert_eq!(
+                    proof.root_from(&leaves[index as usize]).unwrap(),
+                    second_root
+                );
+                // a proof for one leaf doesn't place another there
+                assert_ne!(proof.root_from(&leaf_hash(b"other")).unwrap(), second_root);
+            }
+
+            for first in 0..=second {
+                let proof = memory.consistency_proof(first, second).unwrap();
+                let first_root = memory.root(first).unwrap();
+                assert!(proof.verify(&first_root, &second_root).unwrap());
+                if first > 0 && first < second {
+                    assert!(!proof.verify(&leaf_hash(b"forged"), &second_root).unwrap());
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
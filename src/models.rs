@@ -2,9 +2,59 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::canonical::CanonicalWriter;
+use crate::error::TimeServiceError;
+use crate::imprint::{HashAlgorithm, MessageImprint};
+use crate::merkle::{self, InclusionProof};
+
+/// Domain-separation label for timestamp signing payloads
+pub const TIMESTAMP_SIGNING_DOMAIN: &str = "tsp-authentic-time/timestamp";
+
+/// Domain-separation label for the signed root of a batch of timestamps
+pub const BATCH_SIGNING_DOMAIN: &str = "tsp-authentic-time/batch";
+
+/// Domain-separation label for the leaves of a batch
+pub const BATCH_LEAF_DOMAIN: &str = "tsp-authentic-time/batch-leaf";
+
+/// Domain-separation label for signed tree heads of the transparency log
+pub const TREE_HEAD_SIGNING_DOMAIN: &str = "tsp-authentic-time/tree-head";
+
+/// Domain-separation label for signed refusals (error responses)
+pub const REFUSAL_SIGNING_DOMAIN: &str = "tsp-authentic-time/refusal";
+
+/// Legacy payload format: bare `rfc3339 || nonce` concatenation
+pub const PAYLOAD_VERSION_LEGACY: u8 = 0;
+
+/// Versioned, length-prefixed canonical payload format
+pub const PAYLOAD_VERSION_V1: u8 = 1;
+
+/// Payload format used for newly issued timestamps
+pub const CURRENT_PAYLOAD_VERSION: u8 = PAYLOAD_VERSION_V1;
+
+// Field tags used in the canonical timestamp payload
+const TAG_AUTHORITY_ID: u8 = 1;
+const TAG_NONCE: u8 = 2;
+const TAG_TIME_SECS: u8 = 3;
+const TAG_TIME_NANOS: u8 = 4;
+const TAG_IMPRINT_ALGORITHM: u8 = 5;
+const TAG_IMPRINT_DIGEST: u8 = 6;
+const TAG_KEY_ID: u8 = 7;
+const TAG_STATUS: u8 = 8;
+const TAG_RETRY_AFTER: u8 = 9;
+const TAG_ACCURACY_MICROS: u8 = 10;
+const TAG_PRECISION: u8 = 11;
+const TAG_SERIAL: u8 = 12;
+const TAG_PREVIOUS_HASH: u8 = 13;
+const TAG_BATCH_SIZE: u8 = 14;
+const TAG_BATCH_ROOT: u8 = 15;
+const TAG_TREE_SIZE: u8 = 16;
+const TAG_ROOT_HASH: u8 = 17;
+const TAG_REPLICA_ID: u8 = 18;
 
 /// represents a signed timestamp from a time authority
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AuthenticTimestamp {
     /// time as reported by the authority
     pub timestamp: DateTime<Utc>,
@@ -15,10 +65,131 @@ pub struct AuthenticTimestamp {
     /// authority that issued this timestamp
     pub authority_id: String,
 
-    /// digital signature of the timestamp + nonce by the authority
+    /// hash of the data this timestamp vouches for, if any
+    #[serde(default)]
+    pub message_imprint: Option<MessageImprint>,
+
+    /// identifier of the authority key that signed this timestamp
+    #[serde(default)]
+    pub key_id: Option<String>,
+
+    /// replica of the authority that issued this timestamp; serials and the
+    /// log chain are counted separately by each replica
+    #[serde(default)]
+    pub replica_id: Option<String>,
+
+    /// position in the authority's issuance order; times never decrease with it
+    #[serde(default)]
+    pub serial: Option<u64>,
+
+    /// hash of the timestamp the authority issued before this one
+    #[serde(default)]
+    pub previous_hash: Option<Vec<u8>>,
+
+    /// position of this request in a batch whose root the authority signed
+    #[serde(default)]
+    pub batch: Option<InclusionProof>,
+
+    /// signing payload format version (0 for legacy timestamps)
+    #[serde(default)]
+    pub version: u8,
+
+    /// how far `timestamp` may be from true time (±), if the authority states it
+    #[serde(default)]
+    pub accuracy: Option<Accuracy>,
+
+    /// number of fractional-second digits `timestamp` is given to (0 = whole seconds)
+    #[serde(default)]
+    pub precision: Option<u8>,
+
+    /// digital signature over the signing payload by the authority
     pub signature: Vec<u8>,
 }
 
+/// Accuracy of a timestamp (±), split like RFC 3161's `Accuracy`
+///
+/// Deserialization rejects `millis` or `micros` above 999.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "AccuracyFields")]
+pub struct Accuracy {
+    /// whole seconds
+    #[serde(default)]
+    pub seconds: u64,
+
+    /// additional milliseconds (0-999)
+    #[serde(default)]
+    pub millis: u16,
+
+    /// additional microseconds (0-999)
+    #[serde(default)]
+    pub micros: u16,
+}
+
+impl Accuracy {
+    /// Split a number of microseconds into seconds, millis and micros
+    pub fn from_micros(total: u64) -> Self {
+        Self {
+            seconds: total / 1_000_000,
+            millis: (total / 1_000 % 1_000) as u16,
+            micros: (total % 1_000) as u16,
+        }
+    }
+
+    /// Smallest accuracy that covers `duration`, rounding up to whole microseconds
+    pub fn from_duration(duration: Duration) -> Self {
+        let micros = duration.as_nanos().div_ceil(1_000);
+        Self::from_micros(micros.min(u128::from(u64::MAX)) as u64)
+    }
+
+    /// Total accuracy in microseconds
+    pub fn as_micros(&self) -> u64 {
+        self.seconds
+            .saturating_mul(1_000_000)
+            .saturating_add(u64::from(self.millis) * 1_000)
+            .saturating_add(u64::from(self.micros))
+    }
+
+    /// Total accuracy as a duration
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_micros(self.as_micros())
+    }
+
+    /// Whether `millis` and `micros` are within 0-999
+    pub fn is_valid(&self) -> bool {
+        self.millis < 1_000 && self.micros < 1_000
+    }
+}
+
+// Accuracy as it appears on the wire, before its ranges are checked
+#[derive(Deserialize)]
+struct AccuracyFields {
+    #[serde(default)]
+    seconds: u64,
+    #[serde(default)]
+    millis: u16,
+    #[serde(default)]
+    micros: u16,
+}
+
+impl TryFrom<AccuracyFields> for Accuracy {
+    type Error = String;
+
+    fn try_from(fields: AccuracyFields) -> Result<Self, Self::Error> {
+        let accuracy = Accuracy {
+            seconds: fields.seconds,
+            millis: fields.millis,
+            micros: fields.micros,
+        };
+        match accuracy.is_valid() {
+            true => Ok(accuracy),
+            false => Err(format!(
+                "accuracy millis ({}) and micros ({}) must be 0-999",
+                accuracy.millis, accuracy.micros
+            )),
+        }
+    }
+}
+
 /// Represents a request for an authentic timestamp
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TimestampRequest {
@@ -30,9 +201,20 @@ pub struct TimestampRequest {
 
     /// Optional client public key or identifier
     pub client_id: Option<String>,
+
+    /// Optional hash of the data to be timestamped
+    #[serde(default)]
+    pub message_imprint: Option<MessageImprint>,
+
+    /// Signing payload version the client wants (None accepts the authority's current one)
+    #[serde(default)]
+    pub payload_version: Option<u8>,
 }
 
 /// Represents a response to a timestamp request
+///
+/// Refusals carry the request's nonce and imprint in `timestamp`, signed over
+/// the refusal payload so that a client can prove the authority turned it down.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TimestampResponse {
     /// The authentic timestamp
@@ -40,6 +222,100 @@ pub struct TimestampResponse {
 
     /// Status of the request
     pub status: TimestampStatus,
+
+    /// Seconds the client should wait before retrying a refused request
+    #[serde(default)]
+    pub retry_after_secs: Option<u64>,
+}
+
+/// A signing key an authority uses or has used, with its validity window
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct KeyValidity {
+    /// identifier of the key, as carried in `AuthenticTimestamp::key_id`
+    pub key_id: String,
+
+    /// Ed25519 public key bytes
+    pub public_key: Vec<u8>,
+
+    /// first instant at which the key may sign timestamps
+    pub valid_from: DateTime<Utc>,
+
+    /// instant at which the key was retired (None while it is current)
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+impl KeyValidity {
+    /// Whether a timestamp issued at `time` falls inside this key's window
+    pub fn covers(&self, time: DateTime<Utc>) -> bool {
+        time >= self.valid_from && self.valid_until.is_none_or(|until| time <= until)
+    }
+}
+
+/// Describes a time authority to clients that want to use it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AuthorityMetadata {
+    /// identifier of the authority
+    pub authority_id: String,
+
+    /// current public key used to verify the authority's timestamps
+    pub public_key: Vec<u8>,
+
+    /// current and retired signing keys with their validity windows
+    pub keys: Vec<KeyValidity>,
+
+    /// signing payload version used for new timestamps
+    pub payload_version: u8,
+
+    /// hash algorithms accepted in message imprints
+    pub hash_algorithms: Vec<HashAlgorithm>,
+}
+
+/// The authority's signed statement of its transparency log's size and root
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignedTreeHead {
+    /// authority whose log this is
+    pub authority_id: String,
+
+    /// replica of the authority keeping this log, if it is replicated
+    #[serde(default)]
+    pub replica_id: Option<String>,
+
+    /// number of entries in the log
+    pub tree_size: u64,
+
+    /// Merkle root over the first `tree_size` entries
+    pub root_hash: Vec<u8>,
+
+    /// when the head was signed
+    pub timestamp: DateTime<Utc>,
+
+    /// identifier of the signing key
+    pub key_id: Option<String>,
+
+    /// signature over the canonical payload
+    pub signature: Vec<u8>,
+}
+
+impl SignedTreeHead {
+    /// Build the bytes that the authority signs for this tree head
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut writer = CanonicalWriter::new(TREE_HEAD_SIGNING_DOMAIN, PAYLOAD_VERSION_V1)
+            .string(TAG_AUTHORITY_ID, &self.authority_id)
+            .i64(TAG_TIME_SECS, self.timestamp.timestamp())
+            .u64(
+                TAG_TIME_NANOS,
+                u64::from(self.timestamp.timestamp_subsec_nanos()),
+            )
+            .u64(TAG_TREE_SIZE, self.tree_size)
+            .bytes(TAG_ROOT_HASH, &self.root_hash);
+        if let Some(key_id) = &self.key_id {
+            writer = writer.string(TAG_KEY_ID, key_id);
+        }
+        if let Some(replica_id) = &self.replica_id {
+            writer = writer.string(TAG_REPLICA_ID, replica_id);
+        }
+        writer.finish()
+    }
 }
 
 /// Status codes for timestamp operations
@@ -48,21 +324,205 @@ pub enum TimestampStatus {
     /// Request succeeded
     Success,
 
-    /// Client authentication failed
-    AuthenticationFailed,
+    /// Nonce was already used in a recent request
+    NonceReused,
+
+    /// Client is not authorized to request timestamps
+    #[serde(alias = "AuthenticationFailed")]
+    Unauthorized,
+
+    /// Client exceeded its request rate
+    #[serde(alias = "RateLimitExceeded")]
+    RateLimited,
+
+    /// Authority's clock is not synchronized well enough to issue timestamps
+    ClockUnsynchronized,
 
-    /// Client exceeded rate limit or reused a nonce
-    RateLimitExceeded,
+    /// Requested signing payload version is not supported
+    UnsupportedVersion,
+
+    /// Request is malformed (e.g. an imprint of the wrong length)
+    MalformedRequest,
 
     /// Server encountered an error
     ServerError,
 }
 
+impl TimestampStatus {
+    /// Stable numeric code used in signed refusals
+    pub fn code(&self) -> u64 {
+        match self {
+            TimestampStatus::Success => 0,
+            TimestampStatus::NonceReused => 1,
+            TimestampStatus::Unauthorized => 2,
+            TimestampStatus::RateLimited => 3,
+            TimestampStatus::ClockUnsynchronized => 4,
+            TimestampStatus::UnsupportedVersion => 5,
+            TimestampStatus::MalformedRequest => 6,
+            TimestampStatus::ServerError => 7,
+        }
+    }
+}
+
 impl AuthenticTimestamp {
-    /// Format the message that would be signed (for verification purposes)
+    /// Format the legacy message (`rfc3339 || nonce`) signed by version 0 timestamps
     pub fn format_message(&self) -> String {
         format!("{}{}", self.timestamp.to_rfc3339(), self.nonce)
     }
+
+    /// Build the bytes that the authority signs for this timestamp's version
+    ///
+    /// A legacy timestamp signs only its time and nonce, so one carrying any
+    /// field that only version 1 signs is rejected rather than letting that
+    /// field ride along unsigned.
+    ///
+    /// For a batched timestamp this is the batch root recomputed from the
+    /// inclusion proof, so a proof that doesn't place the request in the
+    /// signed batch fails verification.
+    pub fn signing_payload(&self) -> Result<Vec<u8>, TimeServiceError> {
+        // an out-of-range accuracy would sign a different bound than it states
+        if self.accuracy.is_some_and(|accuracy| !accuracy.is_valid()) {
+            return Err(TimeServiceError::generic(
+                "accuracy millis and micros must be 0-999",
+            ));
+        }
+
+        match self.version {
+            PAYLOAD_VERSION_LEGACY if self.has_v1_fields() => {
+                Err(TimeServiceError::UnsupportedVersion(self.version))
+            }
+            PAYLOAD_VERSION_LEGACY => Ok(self.format_message().into_bytes()),
+            PAYLOAD_VERSION_V1 if self.batch.is_some() => self.batch_root_payload(),
+            PAYLOAD_VERSION_V1 => {
+                let mut writer = CanonicalWriter::new(TIMESTAMP_SIGNING_DOMAIN, self.version)
+                    .string(TAG_AUTHORITY_ID, &self.authority_id)
+                    .string(TAG_NONCE, &self.nonce)
+                    .i64(TAG_TIME_SECS, self.timestamp.timestamp())
+                    .u64(
+                        TAG_TIME_NANOS,
+                        u64::from(self.timestamp.timestamp_subsec_nanos()),
+                    );
+
+                if let Some(imprint) = &self.message_imprint {
+                    writer = writer
+                        .u64(TAG_IMPRINT_ALGORITHM, imprint.hash_algorithm.id())
+                        .bytes(TAG_IMPRINT_DIGEST, &imprint.hashed_message);
+                }
+
+                Ok(self.write_issuance_fields(writer).finish())
+            }
+            other => Err(TimeServiceError::UnsupportedVersion(other)),
+        }
+    }
+
+    // fields describing the issuance itself, shared by every request of a batch
+    fn write_issuance_fields(&self, mut writer: CanonicalWriter) -> CanonicalWriter {
+        if let Some(key_id) = &self.key_id {
+            writer = writer.string(TAG_KEY_ID, key_id);
+        }
+        if let Some(accuracy) = &self.accuracy {
+            writer = writer.u64(TAG_ACCURACY_MICROS, accuracy.as_micros());
+        }
+        if let Some(precision) = self.precision {
+            writer = writer.u64(TAG_PRECISION, u64::from(precision));
+        }
+        if let Some(serial) = self.serial {
+            writer = writer.u64(TAG_SERIAL, serial);
+        }
+        if let Some(previous_hash) = &self.previous_hash {
+            writer = writer.bytes(TAG_PREVIOUS_HASH, previous_hash);
+        }
+        if let Some(replica_id) = &self.replica_id {
+            writer = writer.string(TAG_REPLICA_ID, replica_id);
+        }
+        writer
+    }
+
+    /// Leaf hash of this timestamp in the authority's transparency log
+    ///
+    /// Every request of a batch maps to the same leaf, the signed batch root.
+    pub fn log_leaf_hash(&self) -> Result<Vec<u8>, TimeServiceError> {
+        Ok(merkle::leaf_hash(&self.signing_payload()?))
+    }
+
+    /// Leaf hash of this request (nonce and imprint) in a batch
+    pub fn batch_leaf_hash(&self) -> Vec<u8> {
+        let mut writer =
+            CanonicalWriter::new(BATCH_LEAF_DOMAIN, self.version).string(TAG_NONCE, &self.nonce);
+        if let Some(imprint) = &self.message_imprint {
+            writer = writer
+                .u64(TAG_IMPRINT_ALGORITHM, imprint.hash_algorithm.id())
+                .bytes(TAG_IMPRINT_DIGEST, &imprint.hashed_message);
+        }
+        merkle::leaf_hash(&writer.finish())
+    }
+
+    // the signed batch root, recomputed from this request's inclusion proof
+    fn batch_root_payload(&self) -> Result<Vec<u8>, TimeServiceError> {
+        let proof = self
+            .batch
+            .as_ref()
+            .ok_or_else(|| TimeServiceError::InvalidProof("not part of a batch".to_string()))?;
+        let root = proof.root_from(&self.batch_leaf_hash())?;
+
+        let writer = CanonicalWriter::new(BATCH_SIGNING_DOMAIN, self.version)
+            .string(TAG_AUTHORITY_ID, &self.authority_id)
+            .i64(TAG_TIME_SECS, self.timestamp.timestamp())
+            .u64(
+                TAG_TIME_NANOS,
+                u64::from(self.timestamp.timestamp_subsec_nanos()),
+            );
+        Ok(self
+            .write_issuance_fields(writer)
+            .u64(TAG_BATCH_SIZE, proof.tree_size)
+            .bytes(TAG_BATCH_ROOT, &root)
+            .finish())
+    }
+
+    /// Interval the true time of issuance lies in, if the authority stated its accuracy.
+    ///
+    /// Bounds beyond the representable range are clamped to it.
+    pub fn interval(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let micros = self.accuracy?.as_micros().min(i64::MAX as u64) as i64;
+        let accuracy = chrono::Duration::microseconds(micros);
+        Some((
+            self.timestamp
+                .checked_sub_signed(accuracy)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC),
+            self.timestamp
+                .checked_add_signed(accuracy)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        ))
+    }
+
+    /// Hash identifying this timestamp in the authority's log (SHA-256 of the signing payload)
+    pub fn entry_hash(&self) -> Result<Vec<u8>, TimeServiceError> {
+        Ok(HashAlgorithm::Sha256.digest(&self.signing_payload()?))
+    }
+
+    /// Whether this timestamp uses the legacy, unbound signing format
+    pub fn is_legacy(&self) -> bool {
+        self.version == PAYLOAD_VERSION_LEGACY
+    }
+
+    // whether any field is set that the legacy format doesn't sign
+    fn has_v1_fields(&self) -> bool {
+        self.message_imprint.is_some()
+            || self.key_id.is_some()
+            || self.replica_id.is_some()
+            || self.serial.is_some()
+            || self.previous_hash.is_some()
+            || self.batch.is_some()
+            || self.accuracy.is_some()
+            || self.precision.is_some()
+    }
+
+    /// Check whether this timestamp vouches for the given data
+    pub fn covers_data(&self, data: &[u8]) -> bool {
+        self.message_imprint
+            .as_ref()
+            .is_some_and(|imprint| imprint.matches(data))
+    }
 }
 
 impl TimestampRequest {
@@ -72,6 +532,8 @@ impl TimestampRequest {
             nonce,
             client_signature: None,
             client_id: None,
+            message_imprint: None,
+            payload_version: None,
         }
     }
 
@@ -81,6 +543,71 @@ impl TimestampRequest {
             nonce,
             client_signature: Some(signature),
             client_id: Some(client_id),
+            message_imprint: None,
+            payload_version: None,
         }
     }
+
+    /// Attach the imprint of the data to be timestamped
+    pub fn with_imprint(mut self, imprint: MessageImprint) -> Self {
+        self.message_imprint = Some(imprint);
+        self
+    }
+
+    /// Ask for a specific signing payload version
+    pub fn with_payload_version(mut self, version: u8) -> Self {
+        self.payload_version = Some(version);
+        self
+    }
+}
+
+impl TimestampResponse {
+    /// Whether the authority issued the requested timestamp
+    pub fn is_success(&self) -> bool {
+        self.status == TimestampStatus::Success
+    }
+
+    /// How long the authority asked the client to wait before retrying
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after_secs.map(Duration::from_secs)
+    }
+
+    /// Build the bytes the authority signs for this response.
+    ///
+    /// Successful responses sign the timestamp itself; refusals sign the
+    /// refused request's nonce and imprint together with the status and the
+    /// retry hint under a separate domain, so neither can pass for the other.
+    pub fn signing_payload(&self) -> Result<Vec<u8>, TimeServiceError> {
+        if self.is_success() {
+            return self.timestamp.signing_payload();
+        }
+
+        let ts = &self.timestamp;
+        if ts.version != PAYLOAD_VERSION_V1 {
+            return Err(TimeServiceError::UnsupportedVersion(ts.version));
+        }
+
+        let mut writer = CanonicalWriter::new(REFUSAL_SIGNING_DOMAIN, ts.version)
+            .string(TAG_AUTHORITY_ID, &ts.authority_id)
+            .string(TAG_NONCE, &ts.nonce)
+            .i64(TAG_TIME_SECS, ts.timestamp.timestamp())
+            .u64(
+                TAG_TIME_NANOS,
+                u64::from(ts.timestamp.timestamp_subsec_nanos()),
+            );
+        if let Some(imprint) = &ts.message_imprint {
+            writer = writer
+                .u64(TAG_IMPRINT_ALGORITHM, imprint.hash_algorithm.id())
+                .bytes(TAG_IMPRINT_DIGEST, &imprint.hashed_message);
+        }
+        if let Some(key_id) = &ts.key_id {
+            writer = writer.string(TAG_KEY_ID, key_id);
+        }
+        writer = writer.u64(TAG_STATUS, self.status.code());
+        if let Some(retry_after) = self.retry_after_secs {
+            writer = writer.u64(TAG_RETRY_AFTER, retry_after);
+        }
+
+        Ok(writer.finish())
+    }
 }
@@ -0,0 +1,558 @@
+// Monitoring of authorities for equivocation, with publishable proofs of misbehavior
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::client::TimeClient;
+use crate::error::TimeServiceError;
+use crate::models::{AuthenticTimestamp, SignedTreeHead, PAYLOAD_VERSION_V1};
+use crate::service::TspTimeService;
+
+/// Two signed statements by one authority replica that cannot both be honest
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Misbehavior {
+    /// two tree heads of the same size with different roots: the log was forked
+    ConflictingTreeHeads {
+        first: SignedTreeHead,
+        second: SignedTreeHead,
+    },
+
+    /// two different log entries issued under the same serial
+    ConflictingSerial {
+        first: AuthenticTimestamp,
+        second: AuthenticTimestamp,
+    },
+
+    /// two different log entries linked to the same predecessor: the chain was forked
+    ForkedChain {
+        first: AuthenticTimestamp,
+        second: AuthenticTimestamp,
+    },
+
+    /// a later serial issued at an earlier time
+    TimeReversal {
+        earlier: AuthenticTimestamp,
+        later: AuthenticTimestamp,
+    },
+}
+
+/// Self-contained evidence that an authority misbehaved.
+///
+/// Anyone holding the authority's public keys can check it with `verify`,
+/// without trusting the monitor that produced it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MisbehaviorProof {
+    /// the authority that signed both statements
+    pub authority_id: String,
+
+    /// the contradicting statements
+    pub evidence: Misbehavior,
+
+    /// vantage points the two statements were observed from
+    pub observed_by: (String, String),
+}
+
+impl MisbehaviorProof {
+    /// Check that both statements are validly signed by the authority and contradict each other
+    pub fn verify(&self, verifier: &TimeClient) -> Result<bool, TimeServiceError> {
+        match &self.evidence {
+            Misbehavior::ConflictingTreeHeads { first, second } => Ok(first.authority_id
+                == self.authority_id
+                && second.authority_id == self.authority_id
+                && first.replica_id == second.replica_id
+                && first.tree_size == second.tree_size
+                && first.root_hash != second.root_hash
+                && verifier.verify_tree_head(first)?
+                && verifier.verify_tree_head(second)?),
+            Misbehavior::ConflictingSerial { first, second } => Ok(self
+                .both_signed(verifier, first, second)?
+                && first.serial.is_some()
+                && first.serial == second.serial
+                && first.entry_hash()? != second.entry_hash()?),
+            Misbehavior::ForkedChain { first, second } => Ok(self
+                .both_signed(verifier, first, second)?
+                && first.previous_hash.is_some()
+                && first.previous_hash == second.previous_hash
+                && first.entry_hash()? != second.entry_hash()?),
+            Misbehavior::TimeReversal { earlier, later } => Ok(self
+                .both_signed(verifier, earlier, later)?
+                && earlier.serial.is_some()
+                && earlier.serial < later.serial
+                && earlier.timestamp > later.timestamp),
+        }
+    }
+
+    // legacy timestamps don't sign their serial, chain link or replica, so
+    // they can't show that the authority contradicted itself
+    fn both_signed(
+        &self,
+        verifier: &TimeClient,
+        first: &AuthenticTimestamp,
+        second: &AuthenticTimestamp,
+    ) -> Result<bool, TimeServiceError> {
+        Ok(first.version >= PAYLOAD_VERSION_V1
+            && second.version >= PAYLOAD_VERSION_V1
+            && first.authority_id == self.authority_id
+            && second.authority_id == self.authority_id
+            && first.replica_id == second.replica_id
+            && verifier.verify_timestamp(first)?
+            && verifier.verify_timestamp(second)?)
+    }
+}
+
+/// A statement together with the vantage point it was seen from
+type Seen<T> = (String, T);
+
+/// Authority and replica whose statements must tell one story; replicas of
+/// one authority keep separate serials and logs
+type ViewKey = (String, Option<String>);
+
+#[derive(Default)]
+struct AuthorityView {
+    heads: HashMap<u64, Seen<SignedTreeHead>>,
+    by_serial: BTreeMap<u64, Seen<AuthenticTimestamp>>,
+    by_predecessor: HashMap<Vec<u8>, Seen<AuthenticTimestamp>>,
+}
+
+/// Collects what authorities sign as seen from several vantage points and
+/// checks that they all tell the same story.
+pub struct Monitor {
+    verifier: TimeClient,
+    views: HashMap<ViewKey, AuthorityView>,
+    findings: Vec<MisbehaviorProof>,
+}
+
+impl Monitor {
+    /// Create a monitor that trusts the authority keys known to `verifier`
+    pub fn new(verifier: TimeClient) -> Self {
+        Self {
+            verifier,
+            views: HashMap::new(),
+            findings: Vec::new(),
+        }
+    }
+
+    /// Client used to verify observed statements
+    pub fn verifier(&self) -> &TimeClient {
+        &self.verifier
+    }
+
+    /// Misbehavior found so far
+    pub fn findings(&self) -> &[MisbehaviorProof] {
+        &self.findings
+    }
+
+    /// Record a tree head seen from `vantage`; returns a proof if it contradicts an earlier one.
+    ///
+    /// Heads that don't verify are rejected, since they prove nothing about the authority.
+    pub fn observe_tree_head(
+        &mut self,
+        vantage: &str,
+        head: SignedTreeHead,
+    ) -> Result<Option<MisbehaviorProof>, TimeServiceError> {
+        if !self.verifier.verify_tree_head(&head)? {
+            return Err(TimeServiceError::InvalidSignature);
+        }
+
+        let authority_id = head.authority_id.clone();
+        let key = (authority_id.clone(), head.replica_id.clone());
+        let view = self.views.entry(key).or_default();
+        let (seen_by, seen) = match view.heads.get(&head.tree_size) {
+            None => {
+                view.heads
+                    .insert(head.tree_size, (vantage.to_string(), head));
+                return Ok(None);
+            }
+            Some(seen) if seen.1.root_hash == head.root_hash => return Ok(None),
+            Some(seen) => seen.clone(),
+        };
+
+        Ok(Some(self.report(MisbehaviorProof {
+            authority_id,
+            evidence: Misbehavior::ConflictingTreeHeads {
+                first: seen,
+                second: head,
+            },
+            observed_by: (seen_by, vantage.to_string()),
+        })))
+    }
+
+    /// Record a timestamp seen from `vantage`; returns a proof if it contradicts an earlier one
+    pub fn observe_timestamp(
+        &mut self,
+        vantage: &str,
+        timestamp: AuthenticTimestamp,
+    ) -> Result<Option<MisbehaviorProof>, TimeServiceError> {
+        if !self.verifier.verify_timestamp(&timestamp)? {
+            return Err(TimeServiceError::InvalidSignature);
+        }
+        let Some(serial) = timestamp.serial else {
+            return Ok(None);
+        };
+
+        let authority_id = timestamp.authority_id.clone();
+        let entry_hash = timestamp.entry_hash()?;
+        let key = (authority_id.clone(), timestamp.replica_id.clone());
+        let view = self.views.entry(key).or_default();
+        let seen = (vantage.to_string(), timestamp.clone());
+
+        let evidence = match view.by_serial.get(&serial) {
+            Some((seen_by, other)) if other.entry_hash()? != entry_hash => {
+                let evidence = Misbehavior::ConflictingSerial {
+                    first: other.clone(),
+                    second: timestamp.clone(),
+                };
+                Some((seen_by.clone(), evidence))
+            }
+            _ => Self::fork_or_reversal(view, &timestamp, &entry_hash)?,
+        };
+
+        view.by_serial.entry(serial).or_insert_with(|| seen.clone());
+        if let Some(previous_hash) = &timestamp.previous_hash {
+            view.by_predecessor
+                .entry(previous_hash.clone())
+                .or_insert(seen);
+        }
+
+        Ok(evidence.map(|(seen_by, evidence)| {
+            self.report(MisbehaviorProof {
+                authority_id,
+                evidence,
+                observed_by: (seen_by, vantage.to_string()),
+            })
+        }))
+    }
+
+    // a second entry after the same predecessor, or a time out of order with its neighbours
+    fn fork_or_reversal(
+        view: &AuthorityView,
+        timestamp: &AuthenticTimestamp,
+        entry_hash: &[u8],
+    ) -> Result<Option<(String, Misbehavior)>, TimeServiceError> {
+        if let Some(previous_hash) = &timestamp.previous_hash {
+            if let Some((seen_by, other)) = view.by_predecessor.get(previous_hash) {
+                if other.entry_hash()? != entry_hash {
+                    let evidence = Misbehavior::ForkedChain {
+                        first: other.clone(),
+                        second: timestamp.clone(),
+                    };
+                    return Ok(Some((seen_by.clone(), evidence)));
+                }
+            }
+        }
+
+        let Some(serial) = timestamp.serial else {
+            return Ok(None);
+        };
+        let before = view.by_serial.range(..serial).next_back();
+        if let Some((_, (seen_by, earlier))) = before {
+            if earlier.timestamp > timestamp.timestamp {
+                let evidence = Misbehavior::TimeReversal {
+                    earlier: earlier.clone(),
+                    later: timestamp.clone(),
+                };
+                return Ok(Some((seen_by.clone(), evidence)));
+            }
+        }
+        let after = view.by_serial.range(serial + 1..).next();
+        if let Some((_, (seen_by, later))) = after {
+            if timestamp.timestamp > later.timestamp {
+                let evidence = Misbehavior::TimeReversal {
+                    earlier: timestamp.clone(),
+                    later: later.clone(),
+                };
+                return Ok(Some((seen_by.clone(), evidence)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn report(&mut self, proof: MisbehaviorProof) -> MisbehaviorProof {
+        self.findings.push(proof.clone());
+        proof
+    }
+
+    /// Fetch an authority's tree head through each vantage point and check
+    /// that every pair of heads is consistent.
+    ///
+    /// Same-size heads with different roots yield a proof of misbehavior. For
+    /// heads of different sizes from the same replica, the authority must
+    /// supply a consistency proof;
+    /// if it can't, that is reported as an `InvalidProof` error, since a
+    /// missing proof alone doesn't convince a third party.
+    pub async fn collect_tree_heads(
+        &mut self,
+        authority_id: &str,
+        vantages: &[(&str, &TspTimeService)],
+    ) -> Result<Vec<MisbehaviorProof>, TimeServiceError> {
+        let mut found = Vec::new();
+        let mut heads = Vec::new();
+        for (vantage, service) in vantages {
+            let head = service.get_tree_head(authority_id).await?;
+            found.extend(self.observe_tree_head(vantage, head.clone())?);
+            heads.push((*service, head));
+        }
+
+        heads.sort_by(|(_, a), (_, b)| {
+            (&a.replica_id, a.tree_size).cmp(&(&b.replica_id, b.tree_size))
+        });
+        for pair in heads.windows(2) {
+            let ((_, older), (service, newer)) = (&pair[0], &pair[1]);
+            if older.replica_id != newer.replica_id || older.tree_size == newer.tree_size {
+                continue;
+            }
+            let proof = service
+                .get_consistency_proof(authority_id, older.tree_size, newer.tree_size)
+                .await?;
+            if !self.verifier.verify_consistency(older, newer, &proof)? {
+                return Err(TimeServiceError::InvalidProof(format!(
+                    "{} cannot show that its tree of {} entries extends its tree of {}",
+                    authority_id, newer.tree_size, older.tree_size
+                )));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Fetch a range of an authority's log through each vantage point and
+    /// check the entries against everything seen before
+    pub async fn collect_log(
+        &mut self,
+        authority_id: &str,
+        from: u64,
+        to: u64,
+        vantages: &[(&str, &TspTimeService)],
+    ) -> Result<Vec<MisbehaviorProof>, TimeServiceError> {
+        let mut found = Vec::new();
+        for (vantage, service) in vantages {
+            for timestamp in service.get_log_range(authority_id, from, to).await? {
+                found.extend(self.observe_timestamp(vantage, timestamp)?);
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authority::{TimeAuthority, TimeAuthorityImpl};
+    use crate::chain::LogRange;
+    use crate::models::TimestampRequest;
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    #[tokio::test]
+    async fn test_equivocating_authority_is_caught() {
+        // one key, two diverging logs shown to different clients
+        let keypair = Keypair::generate(&mut OsRng {});
+        let bytes = keypair.to_bytes();
+        let view_a = TimeAuthorityImpl::with_keypair("forked.authority".to_string(), keypair);
+        let view_b = TimeAuthorityImpl::with_keypair(
+            "forked.authority".to_string(),
+            Keypair::from_bytes(&bytes).unwrap(),
+        );
+        for (i, authority) in [&view_a, &view_b].iter().enumerate() {
+            let request = TimestampRequest::new(format!("fork-nonce-{}", i));
+            authority.issue_timestamp(request).await.unwrap();
+        }
+
+        let mut verifier = TimeClient::new_anonymous();
+        verifier
+            .add_authority("forked.authority".to_string(), &view_a.get_public_key())
+            .unwrap();
+        let mut monitor = Monitor::new(verifier);
+
+        assert!(monitor
+            .observe_tree_head("client.a", view_a.signed_tree_head().unwrap())
+            .unwrap()
+            .is_none());
+        let heads = monitor
+            .observe_tree_head("client.b", view_b.signed_tree_head().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            heads.evidence,
+            Misbehavior::ConflictingTreeHeads { .. }
+        ));
+
+        let first_entry = LogRange { from: 1, to: 1 };
+        let entry_a = view_a.log_range(first_entry).unwrap().remove(0);
+        let entry_b = view_b.log_range(first_entry).unwrap().remove(0);
+        monitor.observe_timestamp("client.a", entry_a).unwrap();
+        let serial = monitor
+            .observe_timestamp("client.b", entry_b)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            serial.evidence,
+            Misbehavior::ConflictingSerial { .. }
+        ));
+        assert_eq!(monitor.findings().len(), 2);
+
+        // a third party checks the published proofs with nothing but the authority's key
+        let mut third_party = TimeClient::new_anonymous();
+        third_party
+            .add_authority("forked.authority".to_string(), &view_a.get_public_key())
+            .unwrap();
+        let published: MisbehaviorProof =
+            serde_json::from_slice(&serde_json::to_vec(&serial).unwrap()).unwrap();
+        assert!(published.verify(&third_party).unwrap());
+        assert!(heads.verify(&third_party).unwrap());
+
+        // the same entry seen twice proves nothing
+        let mut honest = serial.clone();
+        if let Misbehavior::ConflictingSerial { first, second } = &mut honest.evidence {
+            *second = first.clone();
+        }
+        assert!(!honest.verify(&third_party).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_honest_replicas_are_not_reported() {
+        // one key and authority id, served by two replicas with their own logs
+        let keypair = Keypair::generate(&mut OsRng {});
+        let bytes = keypair.to_bytes();
+        let mut replica_a =
+            TimeAuthorityImpl::with_keypair("replicated.authority".to_string(), keypair);
+        replica_a.set_replica_id(Some("a".to_string()));
+        let mut replica_b = TimeAuthorityImpl::with_keypair(
+            "replicated.authority".to_string(),
+            Keypair::from_bytes(&bytes).unwrap(),
+        );
+        replica_b.set_replica_id(Some("b".to_string()));
+        for (i, authority) in [&replica_a, &replica_b].iter().enumerate() {
+            let request = TimestampRequest::new(format!("replica-nonce-{}", i));
+            authority.issue_timestamp(request).await.unwrap();
+        }
+
+        let mut verifier = TimeClient::new_anonymous();
+        verifier
+            .add_authority(
+                "replicated.authority".to_string(),
+                &replica_a.get_public_key(),
+            )
+            .unwrap();
+        let mut monitor = Monitor::new(verifier);
+
+        let head_a = replica_a.signed_tree_head().unwrap();
+        let head_b = replica_b.signed_tree_head().unwrap();
+        assert_eq!(head_a.tree_size, head_b.tree_size);
+        assert!(monitor
+            .observe_tree_head("client.a", head_a.clone())
+            .unwrap()
+            .is_none());
+        assert!(monitor
+            .observe_tree_head("client.b", head_b.clone())
+            .unwrap()
+            .is_none());
+
+        let first_entry = LogRange { from: 1, to: 1 };
+        let entry_a = replica_a.log_range(first_entry).unwrap().remove(0);
+        let entry_b = replica_b.log_range(first_entry).unwrap().remove(0);
+        assert_eq!(entry_a.serial, entry_b.serial);
+        assert!(monitor
+            .observe_timestamp("client.a", entry_a.clone())
+            .unwrap()
+            .is_none());
+        assert!(monitor
+            .observe_timestamp("client.b", entry_b.clone())
+            .unwrap()
+            .is_none());
+        assert!(monitor.findings().is_empty());
+
+        // nor can anyone frame the authority with statements from two replicas
+        let framed = MisbehaviorProof {
+            authority_id: "replicated.authority".to_string(),
+            evidence: Misbehavior::ConflictingSerial {
+                first: entry_a,
+                second: entry_b,
+            },
+            observed_by: ("client.a".to_string(), "client.b".to_string()),
+        };
+        assert!(!framed.verify(monitor.verifier()).unwrap());
+        let framed = MisbehaviorProof {
+            evidence: Misbehavior::ConflictingTreeHeads {
+                first: head_a.clone(),
+                second: head_b,
+            },
+            ..framed
+        };
+        assert!(!framed.verify(monitor.verifier()).unwrap());
+
+        // and the replica id is signed, so it can't be stripped to fake a conflict
+        let mut stripped = head_a;
+        stripped.replica_id = None;
+        assert!(!monitor.verifier().verify_tree_head(&stripped).unwrap());
+    }
+
+    #[test]
+    fn test_legacy_timestamps_prove_nothing() {
+        use crate::models::PAYLOAD_VERSION_LEGACY;
+        use chrono::Utc;
+        use ed25519_dalek::Signer;
+
+        let keypair = Keypair::generate(&mut OsRng {});
+        let mut verifier = TimeClient::new_anonymous();
+        verifier
+            .add_authority("honest.authority".to_string(), keypair.public.as_bytes())
+            .unwrap();
+        verifier.set_accept_legacy(true);
+
+        // two genuine legacy timestamps, the older one issued later
+        let legacy = |nonce: &str, age: i64| {
+            let mut timestamp = AuthenticTimestamp {
+                timestamp: Utc::now() - chrono::Duration::seconds(age),
+                nonce: nonce.to_string(),
+                authority_id: "honest.authority".to_string(),
+                message_imprint: None,
+                key_id: None,
+                replica_id: None,
+                serial: None,
+                previous_hash: None,
+                batch: None,
+                version: PAYLOAD_VERSION_LEGACY,
+                accuracy: None,
+                precision: None,
+                signature: vec![],
+            };
+            timestamp.signature = keypair
+                .sign(timestamp.format_message().as_bytes())
+                .to_bytes()
+                .to_vec();
+            timestamp
+        };
+        let (newer, older) = (legacy("legacy-1", 0), legacy("legacy-2", 60));
+        assert!(verifier.verify_timestamp(&newer).unwrap());
+
+        // serials and chain links pinned on afterwards aren't the authority's word
+        let (mut first, mut second) = (newer, older);
+        first.serial = Some(1);
+        second.serial = Some(2);
+        first.previous_hash = Some(vec![0; 32]);
+        second.previous_hash = Some(vec![0; 32]);
+        let mut same_serial = second.clone();
+        same_serial.serial = first.serial;
+        let framed = |evidence| MisbehaviorProof {
+            authority_id: "honest.authority".to_string(),
+            evidence,
+            observed_by: ("client.a".to_string(), "client.b".to_string()),
+        };
+        for evidence in [
+            Misbehavior::TimeReversal {
+                earlier: first.clone(),
+                later: second.clone(),
+            },
+            Misbehavior::ForkedChain {
+                first: first.clone(),
+                second: second.clone(),
+            },
+            Misbehavior::ConflictingSerial {
+                first,
+                second: same_serial,
+            },
+        ] {
+            assert!(!framed(evidence).verify(&verifier).unwrap());
+        }
+    }
+}
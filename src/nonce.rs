@@ -0,0 +1,397 @@
+// Replay protection: stores of recently seen request nonces
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::error::TimeServiceError;
+use crate::journal;
+
+/// Storage for nonces an authority has already served.
+///
+/// Implementations backed by a shared database (e.g. a key-value store with
+/// atomic set-if-absent) let several authority replicas reject each other's
+/// replays; share one instance between in-process replicas with an `Arc`.
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    /// Record a nonce seen at `seen_at`, unless it was already seen within `expiry`.
+    ///
+    /// Returns `false` for a replay. The check and the insert must be atomic.
+    async fn check_and_insert(
+        &self,
+        nonce: &str,
+        seen_at: SystemTime,
+        expiry: Duration,
+    ) -> Result<bool, TimeServiceError>;
+
+    /// Forget nonces seen more than `expiry` before `now`
+    async fn purge_expired(
+        &self,
+        now: SystemTime,
+        expiry: Duration,
+    ) -> Result<(), TimeServiceError>;
+}
+
+fn is_live(seen_at: SystemTime, now: SystemTime, expiry: Duration) -> bool {
+    now.duration_since(seen_at).unwrap_or(Duration::ZERO) < expiry
+}
+
+/// Shards in a `MemoryNonceStore`; requests only contend when their nonces share one
+const SHARDS: usize = 64;
+
+/// One shard of the replay cache, split into two generations.
+///
+/// New nonces go into `current`. Once `current` is `expiry` old it becomes
+/// `previous` and the old `previous` is dropped wholesale, so every nonce is
+/// remembered for at least `expiry` (and at most twice that) without ever
+/// scanning for expired entries.
+struct Shard {
+    started: SystemTime,
+    current: HashMap<String, SystemTime>,
+    previous: HashMap<String, SystemTime>,
+}
+
+impl Shard {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            started: now,
+            current: HashMap::new(),
+            previous: HashMap::new(),
+        }
+    }
+
+    fn rotate(&mut self, now: SystemTime, expiry: Duration) {
+        let age = now.duration_since(self.started).unwrap_or(Duration::ZERO);
+        if age < expiry {
+            return;
+        }
+
+        // after two periods without a rotation nothing in either generation is live
+        self.previous = if age < expiry * 2 {
+            std::mem::take(&mut self.current)
+        } else {
+            self.current.clear();
+            HashMap::new()
+        };
+        self.started = now;
+    }
+
+    fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+}
+
+/// Nonces kept in a sharded, generation-rotating cache in memory; lost when
+/// the process exits
+pub struct MemoryNonceStore {
+    shards: Vec<Mutex<Shard>>,
+    hasher: RandomState,
+}
+
+impl Default for MemoryNonceStore {
+    fn default() -> Self {
+        let now = SystemTime::now();
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::new(now))).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl MemoryNonceStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of nonces currently remembered, including expired ones not yet rotated out
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Whether no nonces are remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn shard(&self, nonce: &str) -> &Mutex<Shard> {
+        let index = self.hasher.hash_one(nonce) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+#[async_trait]
+impl NonceStore for MemoryNonceStore {
+    async fn check_and_insert(
+        &self,
+        nonce: &str,
+        seen_at: SystemTime,
+        expiry: Duration,
+    ) -> Result<bool, TimeServiceError> {
+        let mut shard = self.shard(nonce).lock().unwrap();
+        shard.rotate(seen_at, expiry);
+
+        let replayed = [&shard.current, &shard.previous].iter().any(|generation| {
+            generation
+                .get(nonce)
+                .is_some_and(|&previous| is_live(previous, seen_at, expiry))
+        });
+        if replayed {
+            return Ok(false);
+        }
+
+        shard.current.insert(nonce.to_string(), seen_at);
+        Ok(true)
+    }
+
+    async fn purge_expired(
+        &self,
+        now: SystemTime,
+        expiry: Duration,
+    ) -> Result<(), TimeServiceError> {
+        // shards rotate as they are used; this only frees memory held by idle ones
+        for shard in &self.shards {
+            shard.lock().unwrap().rotate(now, expiry);
+        }
+        Ok(())
+    }
+}
+
+/// One line of the nonce journal
+#[derive(Serialize, Deserialize)]
+struct NonceRecord {
+    nonce: String,
+    seen_at: DateTime<Utc>,
+}
+
+struct Journal {
+    seen: HashMap<String, SystemTime>,
+    file: File,
+
+    /// records in the file, including expired ones not yet compacted away
+    records: usize,
+}
+
+/// Nonces kept in an append-only journal file so replay protection survives restarts.
+///
+/// Every accepted nonce is appended and synced before the timestamp is
+/// issued; the journal is rewritten without expired entries once they make
+/// up most of the file. File I/O runs on tokio's blocking pool so it never
+/// stalls the threads serving requests.
+pub struct FileNonceStore {
+    inner: Arc<JournalFile>,
+}
+
+/// A journal and where it lives, shared with blocking tasks
+struct JournalFile {
+    path: PathBuf,
+    journal: Mutex<Journal>,
+}
+
+impl FileNonceStore {
+    /// Open (or create) a journal, loading the nonces it records
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TimeServiceError> {
+        let path = path.as_ref().to_path_buf();
+        let mut seen = HashMap::new();
+        let mut count = 0;
+        let file = journal::open(&path, |record: NonceRecord| {
+            seen.insert(record.nonce, SystemTime::from(record.seen_at));
+            count += 1;
+            Ok(())
+        })?;
+        Ok(Self {
+            inner: Arc::new(JournalFile {
+                path,
+                journal: Mutex::new(Journal {
+                    seen,
+                    file,
+                    records: count,
+                }),
+            }),
+        })
+    }
+
+    /// Run blocking journal work off the async worker threads
+    async fn blocking<T, F>(&self, work: F) -> Result<T, TimeServiceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&JournalFile) -> Result<T, TimeServiceError> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || work(&inner))
+            .await
+            .map_err(|e| TimeServiceError::generic(format!("nonce journal task failed: {}", e)))?
+    }
+}
+
+impl JournalFile {
+    fn check_and_insert(
+        &self,
+        nonce: &str,
+        seen_at: SystemTime,
+        expiry: Duration,
+    ) -> Result<bool, TimeServiceError> {
+        let mut journal = self.journal.lock().unwrap();
+        if journal
+            .seen
+            .get(nonce)
+            .is_some_and(|&previous| is_live(previous, seen_at, expiry))
+        {
+            return Ok(false);
+        }
+
+        // persist before accepting, so a crash can't forget an issued nonce
+        write_record(&mut journal.file, nonce, seen_at)?;
+        journal.file.sync_data()?;
+        journal.seen.insert(nonce.to_string(), seen_at);
+        journal.records += 1;
+        Ok(true)
+    }
+
+    fn purge_expired(&self, now: SystemTime, expiry: Duration) -> Result<(), TimeServiceError> {
+        let mut journal = self.journal.lock().unwrap();
+        journal
+            .seen
+            .retain(|_, &mut seen_at| is_live(seen_at, now, expiry));
+
+        if journal.records > 2 * journal.seen.len().max(512) {
+            self.compact(&mut journal)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the journal with only the nonces still remembered
+    fn compact(&self, journal: &mut Journal) -> Result<(), TimeServiceError> {
+        // named after the whole file name, so files that differ only in
+        // extension (state.nonces, state.serial) don't share a temp file
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        for (nonce, seen_at) in &journal.seen {
+            write_record(&mut tmp, nonce, *seen_at)?;
+        }
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        journal.file = OpenOptions::new().append(true).open(&self.path)?;
+        journal.records = journal.seen.len();
+        Ok(())
+    }
+}
+
+fn write_record(file: &mut File, nonce: &str, seen_at: SystemTime) -> Result<(), TimeServiceError> {
+    journal::append(
+        file,
+        &NonceRecord {
+            nonce: nonce.to_string(),
+            seen_at: seen_at.into(),
+        },
+    )
+}
+
+#[async_trait]
+impl NonceStore for FileNonceStore {
+    async fn check_and_insert(
+        &self,
+        nonce: &str,
+        seen_at: SystemTime,
+        expiry: Duration,
+    ) -> Result<bool, TimeServiceError> {
+        let nonce = nonce.to_string();
+        self.blocking(move |journal| journal.check_and_insert(&nonce, seen_at, expiry))
+            .await
+    }
+
+    async fn purge_expired(
+        &self,
+        now: SystemTime,
+        expiry: Duration,
+    ) -> Result<(), TimeServiceError> {
+        self.blocking(move |journal| journal.purge_expired(now, expiry))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store_rotates_out_expired_nonces() {
+        let store = MemoryNonceStore::new();
+        let expiry = Duration::from_secs(10);
+        let start = SystemTime::now();
+
+        assert!(store.check_and_insert("n1", start, expiry).await.unwrap());
+        assert!(!store.check_and_insert("n1", start, expiry).await.unwrap());
+
+        // still remembered just before it expires, across a rotation
+        let almost = start + Duration::from_secs(9);
+        store.purge_expired(start + expiry, expiry).await.unwrap();
+        assert!(!store.check_and_insert("n1", almost, expiry).await.unwrap());
+
+        // dropped once two periods pass
+        store
+            .purge_expired(start + expiry * 3, expiry)
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_file_store_survives_reopen() {
+        let path =
+            std::env::temp_dir().join(format!("nonce-journal-{:x}.jsonl", rand::random::<u64>()));
+        let expiry = Duration::from_secs(300);
+        let now = SystemTime::now();
+
+        {
+            let store = FileNonceStore::open(&path).unwrap();
+            assert!(store.check_and_insert("n1", now, expiry).await.unwrap());
+            assert!(!store.check_and_insert("n1", now, expiry).await.unwrap());
+        }
+
+        // a restarted authority still rejects the replay
+        let store = FileNonceStore::open(&path).unwrap();
+        assert!(!store.check_and_insert("n1", now, expiry).await.unwrap());
+
+        // once expired, the nonce is forgotten
+        let later = now + expiry;
+        store.purge_expired(later, expiry).await.unwrap();
+        assert!(store.check_and_insert("n1", later, expiry).await.unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compaction_leaves_neighbouring_files_alone() {
+        let base = std::env::temp_dir().join(format!("state-{:x}", rand::random::<u64>()));
+        let path = base.with_extension("nonces");
+        let neighbour = base.with_extension("tmp");
+        fs::write(&neighbour, b"someone else's").unwrap();
+        let expiry = Duration::from_secs(300);
+        let now = SystemTime::now();
+
+        let store = FileNonceStore::open(&path).unwrap();
+        for i in 0..1100 {
+            let nonce = format!("n{}", i);
+            assert!(store.check_and_insert(&nonce, now, expiry).await.unwrap());
+        }
+        store.purge_expired(now + expiry * 2, expiry).await.unwrap();
+        assert!(store.inner.journal.lock().unwrap().records < 1100);
+
+        assert_eq!(fs::read(&neighbour).unwrap(), b"someone else's");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&neighbour).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+// Tracking of outstanding timestamp requests for request/response binding
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::TimeServiceError;
+use crate::imprint::MessageImprint;
+use crate::models::{AuthenticTimestamp, TimestampRequest};
+
+/// Number of completed nonces remembered to detect duplicated responses
+const COMPLETED_HISTORY: usize = 1024;
+
+/// A timestamp request that has been sent but not yet answered
+struct PendingRequest {
+    /// authority the request was sent to
+    authority_id: String,
+
+    /// imprint the response must carry
+    message_imprint: Option<MessageImprint>,
+
+    /// when the request was sent
+    sent_at: Instant,
+}
+
+/// A response that matched an outstanding request
+#[derive(Clone, Debug)]
+pub struct TrackedResponse {
+    /// the validated timestamp
+    pub timestamp: AuthenticTimestamp,
+
+    /// time between sending the request and accepting the response
+    pub rtt: Duration,
+
+    /// when the response was accepted
+    pub received_at: Instant,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    pending: HashMap<String, PendingRequest>,
+    completed: HashSet<String>,
+    completed_order: VecDeque<String>,
+}
+
+/// Outstanding requests keyed by nonce
+#[derive(Default)]
+pub struct PendingRequests {
+    state: Mutex<TrackerState>,
+}
+
+impl PendingRequests {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request about to be sent to an authority
+    pub fn track(
+        &self,
+        request: &TimestampRequest,
+        authority_id: &str,
+    ) -> Result<(), TimeServiceError> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.contains_key(&request.nonce) || state.completed.contains(&request.nonce) {
+            return Err(TimeServiceError::NonceReused);
+        }
+
+        state.pending.insert(
+            request.nonce.clone(),
+            PendingRequest {
+                authority_id: authority_id.to_string(),
+                message_imprint: request.message_imprint.clone(),
+                sent_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Forget a request that failed before a response arrived
+    pub fn cancel(&self, nonce: &str) {
+        self.state.lock().unwrap().pending.remove(nonce);
+    }
+
+    /// Check that a response answers an outstanding request, without consuming it.
+    ///
+    /// Responses for nonces we never sent, for requests that were already
+    /// answered, or that don't echo the request's authority and imprint are
+    /// rejected.
+    pub fn check(&self, timestamp: &AuthenticTimestamp) -> Result<(), TimeServiceError> {
+        let state = self.state.lock().unwrap();
+        Self::matching(&state, timestamp).map(|_| ())
+    }
+
+    /// Consume the outstanding request a verified response answers and
+    /// measure the round trip.
+    ///
+    /// Callers check the response's signature first, so a forged or
+    /// mismatched reply leaves the request open for the genuine one.
+    pub fn complete(
+        &self,
+        timestamp: AuthenticTimestamp,
+    ) -> Result<TrackedResponse, TimeServiceError> {
+        let mut state = self.state.lock().unwrap();
+        let sent_at = Self::matching(&state, &timestamp)?;
+        state.pending.remove(&timestamp.nonce);
+
+        // a second reply is a duplicate
+        state.completed.insert(timestamp.nonce.clone());
+        state.completed_order.push_back(timestamp.nonce.clone());
+        if state.completed_order.len() > COMPLETED_HISTORY {
+            if let Some(oldest) = state.completed_order.pop_front() {
+                state.completed.remove(&oldest);
+            }
+        }
+
+        let received_at = Instant::now();
+        Ok(TrackedResponse {
+            timestamp,
+            rtt: received_at - sent_at,
+            received_at,
+        })
+    }
+
+    /// when the request a response answers was sent
+    fn matching(
+        state: &TrackerState,
+        timestamp: &AuthenticTimestamp,
+    ) -> Result<Instant, TimeServiceError> {
+        let pending = match state.pending.get(&timestamp.nonce) {
+            Some(pending) => pending,
+            None if state.completed.contains(&timestamp.nonce) => {
+                return Err(TimeServiceError::DuplicateResponse(timestamp.nonce.clone()))
+            }
+            None => {
+                return Err(TimeServiceError::UnsolicitedResponse(
+                    timestamp.nonce.clone(),
+                ))
+            }
+        };
+
+        if timestamp.authority_id != pending.authority_id {
+            return Err(TimeServiceError::ResponseMismatch(format!(
+                "issued by '{}' instead of '{}'",
+                timestamp.authority_id, pending.authority_id
+            )));
+        }
+        if timestamp.message_imprint != pending.message_imprint {
+            return Err(TimeServiceError::ResponseMismatch(
+                "message imprint differs from the request".to_string(),
+            ));
+        }
+        Ok(pending.sent_at)
+    }
+
+    /// Number of requests still awaiting a response
+    pub fn outstanding(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Drop outstanding requests older than `max_age`; returns how many were dropped
+    pub fn expire(&self, max_age: Duration) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let before = state.pending.len();
+        state
+            .pending
+            .retain(|_, pending| pending.sent_at.elapsed() <= max_age);
+        before - state.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn response_for(request: &TimestampRequest, authority_id: &str) -> AuthenticTimestamp {
+        AuthenticTimestamp {
+            timestamp: Utc::now(),
+            nonce: request.nonce.clone(),
+            authority_id: authority_id.to_string(),
+            message_imprint: request.message_imprint.clone(),
+            key_id: None,
+            replica_id: None,
+            serial: None,
+            previous_hash: None,
+            batch: None,
+            version: crate::models::CURRENT_PAYLOAD_VERSION,
+            accuracy: None,
+            precision: None,
+            signature: vec![],
+        }
+    }
+
+    #[test]
+    fn test_rejects_unsolicited_duplicate_and_mismatched_responses() {
+        let tracker = PendingRequests::new();
+        let request = TimestampRequest::new("tracked-nonce".to_string());
+        tracker.track(&request, "authority.a").unwrap();
+        assert_eq!(tracker.outstanding(), 1);
+
+        // a response to a request we never sent
+        let stranger = TimestampRequest::new("stranger".to_string());
+        assert!(matches!(
+            tracker.complete(response_for(&stranger, "authority.a")),
+            Err(TimeServiceError::UnsolicitedResponse(_))
+        ));
+
+        let response = response_for(&request, "authority.a");
+        assert!(tracker.complete(response.clone()).is_ok());
+        assert_eq!(tracker.outstanding(), 0);
+        assert!(matches!(
+            tracker.complete(response),
+            Err(TimeServiceError::DuplicateResponse(_))
+        ));
+
+        // a response from a different authority than the one asked doesn't
+        // use up the request
+        let other = TimestampRequest::new("other-nonce".to_string());
+        tracker.track(&other, "authority.a").unwrap();
+        assert!(matches!(
+            tracker.complete(response_for(&other, "authority.b")),
+            Err(TimeServiceError::ResponseMismatch(_))
+        ));
+        assert!(tracker.check(&response_for(&other, "authority.a")).is_ok());
+        assert_eq!(tracker.outstanding(), 1);
+        assert!(tracker
+            .complete(response_for(&other, "authority.a"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_expire_drops_abandoned_requests() {
+        let tracker = PendingRequests::new();
+        let abandoned = TimestampRequest::new("abandoned".to_string());
+        tracker.track(&abandoned, "authority.a").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let fresh = TimestampRequest::new("fresh".to_string());
+        tracker.track(&fresh, "authority.a").unwrap();
+        assert_eq!(tracker.expire(Duration::from_millis(10)), 1);
+        assert_eq!(tracker.outstanding(), 1);
+
+        // a late response to the dropped request is no longer accepted
+        assert!(matches!(
+            tracker.complete(response_for(&abandoned, "authority.a")),
+            Err(TimeServiceError::UnsolicitedResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+// Verification policy and structured verdicts for received timestamps
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Rules a relying party applies on top of signature verification
+#[derive(Clone, Debug)]
+pub struct VerificationPolicy {
+    /// oldest acceptable timestamp (None accepts any age)
+    pub max_age: Option<Duration>,
+
+    /// how far ahead of the local clock a timestamp may be
+    pub max_future_skew: Duration,
+
+    /// authorities whose timestamps are acceptable (None accepts any known authority)
+    pub required_authorities: Option<HashSet<String>>,
+
+    /// worst acceptable stated accuracy (None accepts timestamps without one)
+    pub max_accuracy: Option<Duration>,
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_future_skew: Duration::from_secs(5),
+            required_authorities: None,
+            max_accuracy: None,
+        }
+    }
+}
+
+impl VerificationPolicy {
+    /// Create a policy that only checks signatures and a 5 second future skew
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject timestamps older than `max_age`
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Reject timestamps more than `skew` ahead of the local clock
+    pub fn with_max_future_skew(mut self, skew: Duration) -> Self {
+        self.max_future_skew = skew;
+        self
+    }
+
+    /// Only accept timestamps from the given authorities
+    pub fn with_required_authorities<I: IntoIterator<Item = String>>(mut self, ids: I) -> Self {
+        self.required_authorities = Some(ids.into_iter().collect());
+        self
+    }
+
+    /// Only accept timestamps stating an accuracy of `accuracy` or better
+    pub fn with_max_accuracy(mut self, accuracy: Duration) -> Self {
+        self.max_accuracy = Some(accuracy);
+        self
+    }
+
+    /// Whether the policy accepts timestamps from this authority at all
+    pub fn allows_authority(&self, authority_id: &str) -> bool {
+        self.required_authorities
+            .as_ref()
+            .is_none_or(|ids| ids.contains(authority_id))
+    }
+}
+
+/// Outcome of evaluating a timestamp against a verification policy
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    /// signature verifies and the timestamp satisfies the policy
+    Valid,
+
+    /// timestamp is older than the policy's maximum age
+    Stale { age: Duration },
+
+    /// timestamp is further in the future than the allowed skew
+    FromTheFuture { ahead: Duration },
+
+    /// timestamp states no accuracy, or a worse one than the policy requires
+    Inaccurate { accuracy: Option<Duration> },
+
+    /// authority is not trusted or not in the required set
+    UnknownAuthority(String),
+
+    /// signature, key or payload format did not verify
+    BadSignature(String),
+}
+
+impl Verdict {
+    /// Whether the timestamp should be accepted
+    pub fn is_valid(&self) -> bool {
+        *self == Verdict::Valid
+    }
+}
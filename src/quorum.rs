@@ -0,0 +1,303 @@
+// Quorum timestamps: one point in time vouched for by several authorities
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::consensus::{self, Consensus, TimeSample};
+use crate::error::TimeServiceError;
+use crate::models::AuthenticTimestamp;
+
+/// An authority that did not contribute a valid timestamp to a quorum
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuorumFailure {
+    /// authority that was asked
+    pub authority_id: String,
+
+    /// why its response was not counted
+    pub reason: String,
+}
+
+/// A bundle of signed timestamps from several authorities with their consensus time
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuorumTimestamp {
+    /// valid signed timestamps, one per responding authority
+    pub timestamps: Vec<AuthenticTimestamp>,
+
+    /// observation uncertainty of each timestamp, in the same order
+    pub samples: Vec<TimeSample>,
+
+    /// number of distinct authorities that had to agree
+    pub threshold: usize,
+
+    /// intersection of the samples' confidence intervals
+    pub consensus: Consensus,
+
+    /// consensus time (midpoint of the agreed interval)
+    pub consensus_time: DateTime<Utc>,
+
+    /// earliest time reported by any authority
+    pub earliest: DateTime<Utc>,
+
+    /// latest time reported by any authority
+    pub latest: DateTime<Utc>,
+
+    /// authorities whose responses were missing or invalid
+    #[serde(default)]
+    pub failures: Vec<QuorumFailure>,
+}
+
+impl QuorumTimestamp {
+    /// Build a quorum bundle, failing if fewer than `threshold` distinct
+    /// authorities agree on the time
+    pub fn new(
+        timestamps: Vec<AuthenticTimestamp>,
+        samples: Vec<TimeSample>,
+        threshold: usize,
+        mut failures: Vec<QuorumFailure>,
+    ) -> Result<Self, TimeServiceError> {
+        let consensus = consensus::marzullo(&samples);
+        let obtained = consensus
+            .as_ref()
+            .map_or(0, |c| distinct_ids(c.agreeing.iter().map(String::as_str)));
+        let (consensus, (earliest, latest)) = match (consensus, summarize(&timestamps)) {
+            (Some(consensus), Some(range)) if threshold > 0 && obtained >= threshold => {
+                (consensus, range)
+            }
+            _ => {
+                return Err(TimeServiceError::QuorumNotReached {
+                    required: threshold,
+                    obtained,
+                })
+            }
+        };
+
+        for authority_id in &consensus.suspected_faulty {
+            failures.push(QuorumFailure {
+                authority_id: authority_id.clone(),
+                reason: "time outside the agreed interval".to_string(),
+            });
+        }
+
+        Ok(Self {
+            timestamps,
+            samples,
+            threshold,
+            consensus_time: consensus.time(),
+            consensus,
+            earliest,
+            latest,
+            failures,
+        })
+    }
+
+    /// Difference between the latest and earliest reported times
+    pub fn spread(&self) -> Duration {
+        self.latest - self.earliest
+    }
+
+    /// Number of distinct authorities that agree on the consensus interval
+    pub fn agreeing_authorities(&self) -> usize {
+        distinct_ids(self.consensus.agreeing.iter().map(String::as_str))
+    }
+
+    /// Whether every timestamp vouches for the same data (or none does)
+    pub fn shares_imprint(&self) -> bool {
+        self.timestamps
+            .windows(2)
+            .all(|pair| pair[0].message_imprint == pair[1].message_imprint)
+    }
+
+    /// Recompute the consensus from what the authorities signed.
+    ///
+    /// Round trips and ages in `samples` are the requester's word, so they are
+    /// replaced by `max_rtt`, the verifier's bound on how far apart in time
+    /// the authorities may have been asked; each interval is the signed time
+    /// widened by the signed accuracy. Timestamps further apart than that
+    /// bound allows don't intersect, so they can't count as agreeing.
+    pub fn signed_consensus(&self, max_rtt: std::time::Duration) -> Option<Consensus> {
+        let rtt_micros = max_rtt.as_micros().min(u128::from(u64::MAX)) as u64;
+        let samples: Vec<TimeSample> = self
+            .timestamps
+            .iter()
+            .map(|ts| TimeSample {
+                authority_id: ts.authority_id.clone(),
+                time: ts.timestamp,
+                rtt_micros,
+                age_micros: 0,
+                accuracy_micros: ts.accuracy.map_or(0, |accuracy| accuracy.as_micros()),
+            })
+            .collect();
+        consensus::marzullo(&samples)
+    }
+
+    /// Check that the samples describe the timestamps and that the summary
+    /// fields match what they were computed from
+    pub fn is_consistent(&self) -> bool {
+        let samples_match = self.samples.len() == self.timestamps.len()
+            && self
+                .samples
+                .iter()
+                .zip(&self.timestamps)
+                .all(|(sample, ts)| {
+                    sample.authority_id == ts.authority_id && sample.time == ts.timestamp
+                });
+
+        samples_match
+            && consensus::marzullo(&self.samples).as_ref() == Some(&self.consensus)
+            && self.consensus_time == self.consensus.time()
+            && summarize(&self.timestamps) == Some((self.earliest, self.latest))
+    }
+}
+
+/// Number of distinct authorities among the timestamps
+pub fn distinct_authorities(timestamps: &[AuthenticTimestamp]) -> usize {
+    distinct_ids(timestamps.iter().map(|ts| ts.authority_id.as_str()))
+}
+
+fn distinct_ids<'a>(ids: impl Iterator<Item = &'a str>) -> usize {
+    ids.collect::<HashSet<_>>().len()
+}
+
+/// Earliest and latest time of a set of timestamps
+fn summarize(timestamps: &[AuthenticTimestamp]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let earliest = timestamps.iter().map(|ts| ts.timestamp).min()?;
+    let latest = timestamps.iter().map(|ts| ts.timestamp).max()?;
+    Some((earliest, latest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authority::{TimeAuthority, TimeAuthorityImpl};
+    use crate::client::TimeClient;
+    use crate::clock::ManualClock;
+    use crate::loopback::LoopbackNetwork;
+    use crate::models::TimestampRequest;
+    use std::sync::Arc;
+
+    const MAX_RTT: std::time::Duration = std::time::Duration::from_secs(1);
+
+    #[tokio::test]
+    async fn test_quorum_over_loopback() {
+        let network = LoopbackNetwork::default();
+        let authorities: Vec<String> = ["authority.a", "authority.b", "authority.c"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        for id in &authorities {
+            network.spawn_authority(id);
+        }
+        let client = network.spawn_client(None).unwrap();
+
+        // An unreachable fourth authority is reported but doesn't prevent a 3-of-4 quorum
+        let mut asked = authorities.clone();
+        asked.push("authority.offline".to_string());
+        let quorum = client
+            .request_quorum_timestamp(&asked, 3, Some(b"quorum document"))
+            .await
+            .unwrap();
+
+        assert_eq!(quorum.timestamps.len(), 3);
+        assert_eq!(quorum.failures.len(), 1);
+        assert_eq!(quorum.failures[0].authority_id, "authority.offline");
+        assert!(quorum.consensus.suspected_faulty.is_empty());
+        assert_eq!(quorum.agreeing_authorities(), 3);
+        assert!(quorum
+            .timestamps
+            .iter()
+            .all(|ts| ts.covers_data(b"quorum document")));
+
+        // An independent verifier re-checks every signature and the threshold
+        let verifier = network.spawn_client(None).unwrap();
+        assert!(verifier
+            .verify_quorum_timestamp(&quorum, 3, MAX_RTT)
+            .unwrap());
+        assert!(!verifier
+            .verify_quorum_timestamp(&quorum, 4, MAX_RTT)
+            .unwrap());
+
+        // Tampering with the consensus summary is detected
+        let mut tampered = quorum.clone();
+        tampered.consensus_time = tampered.latest + chrono::Duration::seconds(60);
+        assert!(!verifier
+            .verify_quorum_timestamp(&tampered, 3, MAX_RTT)
+            .unwrap());
+
+        // Asking for more agreement than is available fails
+        let result = client.request_quorum_timestamp(&asked, 4, None).await;
+        assert!(matches!(
+            result,
+            Err(TimeServiceError::QuorumNotReached {
+                required: 4,
+                obtained: 3
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_round_trips_cannot_make_timestamps_agree() {
+        let mut verifier = TimeClient::new_anonymous();
+        let mut authorities = Vec::new();
+        let now = Utc::now();
+        for (id, years_ago) in [("authority.a", 0), ("authority.b", 0), ("authority.c", 2)] {
+            let mut authority = TimeAuthorityImpl::new(id.to_string());
+            let then = now - Duration::days(365 * years_ago);
+            authority.set_clock(Arc::new(ManualClock::new(then)));
+            verifier
+                .add_authority(id.to_string(), &authority.get_public_key())
+                .unwrap();
+            authorities.push(authority);
+        }
+
+        let mut timestamps = Vec::new();
+        for (i, authority) in authorities.iter().enumerate() {
+            let request = TimestampRequest::new(format!("quorum-nonce-{}", i)).with_imprint(
+                crate::imprint::MessageImprint::from_data(
+                    crate::imprint::HashAlgorithm::Sha256,
+                    b"quorum document",
+                ),
+            );
+            let response = authority.issue_timestamp(request).await.unwrap();
+            timestamps.push(response.timestamp);
+        }
+
+        // round trips of years make the bundle agree with itself
+        let samples = timestamps
+            .iter()
+            .map(|ts| TimeSample {
+                authority_id: ts.authority_id.clone(),
+                time: ts.timestamp,
+                rtt_micros: 3 * 365 * 24 * 3600 * 1_000_000,
+                age_micros: 0,
+                accuracy_micros: 0,
+            })
+            .collect();
+        let forged = QuorumTimestamp::new(timestamps.clone(), samples, 3, vec![]).unwrap();
+        assert!(forged.is_consistent());
+
+        // but the signed times are years apart
+        assert!(!verifier
+            .verify_quorum_timestamp(&forged, 3, MAX_RTT)
+            .unwrap());
+
+        // the two recent timestamps agree, but only if they vouch for the same data
+        let samples = timestamps[..2]
+            .iter()
+            .map(|ts| TimeSample {
+                authority_id: ts.authority_id.clone(),
+                time: ts.timestamp,
+                rtt_micros: 1,
+                age_micros: 0,
+                accuracy_micros: 0,
+            })
+            .collect();
+        let honest = QuorumTimestamp::new(timestamps[..2].to_vec(), samples, 2, vec![]).unwrap();
+        assert!(verifier
+            .verify_quorum_timestamp(&honest, 2, MAX_RTT)
+            .unwrap());
+        let mut mixed = honest;
+        mixed.timestamps[1].message_imprint = None;
+        assert!(!mixed.shares_imprint());
+    }
+}
@@ -0,0 +1,296 @@
+// Token-bucket rate limiting for timestamp issuance
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::TimeServiceError;
+
+/// Most requester buckets tracked at once
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// Slowest refill rate accepted, besides zero (one token per ~12 days)
+const MIN_PER_SECOND: f64 = 1e-6;
+
+/// Size and refill rate of a token bucket
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// requests that may be made back to back
+    pub burst: u32,
+
+    /// requests added back to the bucket per second
+    pub per_second: f64,
+}
+
+impl RateLimit {
+    /// Allow `per_second` requests per second with bursts of up to `burst`.
+    ///
+    /// A rate of zero allows `burst` requests in total; negative, non-finite
+    /// and vanishingly small rates are rejected.
+    pub fn new(burst: u32, per_second: f64) -> Result<Self, TimeServiceError> {
+        if !(per_second == 0.0 || (MIN_PER_SECOND..f64::INFINITY).contains(&per_second)) {
+            return Err(TimeServiceError::generic(format!(
+                "invalid refill rate {} per second",
+                per_second
+            )));
+        }
+        Ok(Self { burst, per_second })
+    }
+}
+
+/// Quotas applied by an authority; `None` leaves that dimension unlimited
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimitConfig {
+    /// quota for each authenticated client, keyed by `client_id`
+    pub authenticated: Option<RateLimit>,
+
+    /// quota for each anonymous source (network address, or all unknown sources together)
+    pub anonymous: Option<RateLimit>,
+
+    /// quota shared by every request the authority serves
+    pub global: Option<RateLimit>,
+}
+
+impl RateLimitConfig {
+    /// Limit each authenticated client
+    pub fn with_authenticated(mut self, limit: RateLimit) -> Self {
+        self.authenticated = Some(limit);
+        self
+    }
+
+    /// Limit each anonymous source
+    pub fn with_anonymous(mut self, limit: RateLimit) -> Self {
+        self.anonymous = Some(limit);
+        self
+    }
+
+    /// Limit the authority as a whole
+    pub fn with_global(mut self, limit: RateLimit) -> Self {
+        self.global = Some(limit);
+        self
+    }
+}
+
+/// Who a request is charged to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Requester {
+    /// client whose identity the authority verified
+    Authenticated(String),
+
+    /// unauthenticated request from a source address, if known
+    Anonymous(Option<String>),
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+
+    /// when the requester was last seen, in `LimiterState::tick`s
+    last_seen: u64,
+}
+
+impl TokenBucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated: now,
+            last_seen: 0,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    /// time until a token is available (`Duration::MAX` if never), or None if one is available now
+    fn wait(&self, limit: &RateLimit) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        // the fields are public, so don't trust the rate to give a representable wait
+        Some(
+            Duration::try_from_secs_f64((1.0 - self.tokens) / limit.per_second)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+#[derive(Default)]
+struct LimiterState {
+    requesters: HashMap<Requester, TokenBucket>,
+    global: Option<TokenBucket>,
+
+    /// requesters by when they were last seen, least recent first
+    recency: BTreeMap<u64, Requester>,
+    tick: u64,
+}
+
+/// Per-requester and global token buckets
+#[derive(Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    /// Create a limiter enforcing the given quotas
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// Quotas enforced by this limiter
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Take a token for the requester, or return how long to wait for one
+    /// (None if no token will ever be available).
+    ///
+    /// Tokens are only taken when both the requester's and the global bucket
+    /// have one, so refused requests don't eat into anyone's quota. Once
+    /// `MAX_TRACKED_BUCKETS` requesters are tracked, the one seen least
+    /// recently is forgotten to make room, so a flood of new identities can't
+    /// lock out anyone else.
+    pub fn check(&self, requester: &Requester, now: Instant) -> Result<(), Option<Duration>> {
+        let limit = match requester {
+            Requester::Authenticated(_) => self.config.authenticated,
+            Requester::Anonymous(_) => self.config.anonymous,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let mut wait = None;
+        let mut requester_bucket = None;
+        if let Some(limit) = limit {
+            if state.requesters.len() >= MAX_TRACKED_BUCKETS
+                && !state.requesters.contains_key(requester)
+            {
+                if let Some((_, oldest)) = state.recency.pop_first() {
+                    state.requesters.remove(&oldest);
+                }
+            }
+
+            let bucket = state
+                .requesters
+                .entry(requester.clone())
+                .or_insert_with(|| TokenBucket::full(&limit, now));
+            state.tick += 1;
+            state.recency.remove(&bucket.last_seen);
+            state.recency.insert(state.tick, requester.clone());
+            bucket.last_seen = state.tick;
+
+            bucket.refill(&limit, now);
+            wait = wait.max(bucket.wait(&limit));
+            requester_bucket = Some(bucket);
+        }
+        let global_bucket = self.config.global.map(|limit| {
+            let bucket = state
+                .global
+                .get_or_insert_with(|| TokenBucket::full(&limit, now));
+            bucket.refill(&limit, now);
+            wait = wait.max(bucket.wait(&limit));
+            bucket
+        });
+
+        if let Some(wait) = wait {
+            return Err((wait < Duration::MAX).then_some(wait));
+        }
+        for bucket in requester_bucket.into_iter().chain(global_bucket) {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_refill_and_are_separate_per_requester() {
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default()
+                .with_authenticated(RateLimit::new(2, 1.0).unwrap())
+                .with_anonymous(RateLimit::new(1, 0.5).unwrap()),
+        );
+        let alice = Requester::Authenticated("alice".to_string());
+        let bob = Requester::Authenticated("bob".to_string());
+        let anonymous = Requester::Anonymous(Some("192.0.2.1".to_string()));
+        let start = Instant::now();
+
+        assert!(limiter.check(&alice, start).is_ok());
+        assert!(limiter.check(&alice, start).is_ok());
+        let wait = limiter.check(&alice, start).unwrap_err();
+        assert_eq!(wait, Some(Duration::from_secs(1)));
+
+        // other requesters have their own buckets and quotas
+        assert!(limiter.check(&bob, start).is_ok());
+        assert!(limiter.check(&anonymous, start).is_ok());
+        assert_eq!(
+            limiter.check(&anonymous, start).unwrap_err(),
+            Some(Duration::from_secs(2))
+        );
+
+        // tokens come back over time
+        assert!(limiter
+            .check(&alice, start + Duration::from_secs(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_global_limit_applies_across_requesters() {
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default().with_global(RateLimit::new(2, 1.0).unwrap()),
+        );
+        let now = Instant::now();
+
+        assert!(limiter
+            .check(&Requester::Authenticated("a".to_string()), now)
+            .is_ok());
+        assert!(limiter.check(&Requester::Anonymous(None), now).is_ok());
+        assert!(limiter
+            .check(&Requester::Authenticated("b".to_string()), now)
+            .is_err());
+    }
+
+    #[test]
+    fn test_burst_only_limits_invalid_rates_and_bucket_cap() {
+        for rate in [-1.0, f64::NAN, f64::INFINITY, 1e-12] {
+            assert!(RateLimit::new(1, rate).is_err(), "{}", rate);
+        }
+
+        // a burst-only quota never refills, so there is no wait to hint
+        let limiter = RateLimiter::new(
+            RateLimitConfig::default().with_anonymous(RateLimit::new(1, 0.0).unwrap()),
+        );
+        let source = Requester::Anonymous(None);
+        let now = Instant::now();
+        assert!(limiter.check(&source, now).is_ok());
+        assert_eq!(limiter.check(&source, now), Err(None));
+
+        // once the table is full, newcomers displace whoever was seen least recently
+        for i in 1..MAX_TRACKED_BUCKETS {
+            let requester = Requester::Anonymous(Some(i.to_string()));
+            assert!(limiter.check(&requester, now).is_ok());
+        }
+        assert!(limiter.check(&source, now).is_err());
+        let newcomer = Requester::Anonymous(Some("newcomer".to_string()));
+        assert!(limiter.check(&newcomer, now).is_ok());
+
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.requesters.len(), MAX_TRACKED_BUCKETS);
+        assert_eq!(state.recency.len(), MAX_TRACKED_BUCKETS);
+        assert!(!state
+            .requesters
+            .contains_key(&Requester::Anonymous(Some("1".to_string()))));
+        drop(state);
+        // the recently seen source kept its spent bucket
+        assert_eq!(limiter.check(&source, now), Err(None));
+    }
+}
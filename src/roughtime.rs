@@ -0,0 +1,720 @@
+// Roughtime wire format, UDP server mode, client helpers and chained evidence
+//
+// Follows the original (Google) Roughtime protocol: tagged little-endian
+// messages, SHA-512 Merkle trees over request nonces, and an online key
+// delegated by the authority's long-term Ed25519 key.
+
+use chrono::{DateTime, TimeZone, Utc};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+use crate::authority::TimeAuthorityImpl;
+use crate::error::TimeServiceError;
+use crate::models::AuthenticTimestamp;
+use crate::service::TspTimeService;
+
+/// Prefix of the long-term key's signature over a delegation
+pub const DELEGATION_CONTEXT: &[u8] = b"RoughTime v1 delegation signature--\x00";
+
+/// Prefix of the online key's signature over a signed response
+pub const RESPONSE_CONTEXT: &[u8] = b"RoughTime v1 response signature\x00";
+
+/// Length of a request nonce
+pub const NONCE_SIZE: usize = 64;
+
+/// Requests are padded to at least this size so responses never amplify traffic
+pub const MIN_REQUEST_SIZE: usize = 1024;
+
+/// How long a client waits for a Roughtime response
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Radius stated when the authority doesn't know its clock error
+const DEFAULT_RADIUS: Duration = Duration::from_secs(1);
+
+/// How long a delegated online key is valid
+const DELEGATION_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Largest datagram read by the server
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+const fn tag(name: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*name)
+}
+
+pub const TAG_SIG: u32 = tag(b"SIG\x00");
+pub const TAG_NONC: u32 = tag(b"NONC");
+pub const TAG_PAD: u32 = tag(b"PAD\xff");
+pub const TAG_PATH: u32 = tag(b"PATH");
+pub const TAG_SREP: u32 = tag(b"SREP");
+pub const TAG_CERT: u32 = tag(b"CERT");
+pub const TAG_INDX: u32 = tag(b"INDX");
+pub const TAG_ROOT: u32 = tag(b"ROOT");
+pub const TAG_MIDP: u32 = tag(b"MIDP");
+pub const TAG_RADI: u32 = tag(b"RADI");
+pub const TAG_DELE: u32 = tag(b"DELE");
+pub const TAG_MINT: u32 = tag(b"MINT");
+pub const TAG_MAXT: u32 = tag(b"MAXT");
+pub const TAG_PUBK: u32 = tag(b"PUBK");
+
+fn malformed(message: &str) -> TimeServiceError {
+    TimeServiceError::RoughtimeError(message.to_string())
+}
+
+/// A Roughtime message: tagged values, encoded in ascending tag order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    fields: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Message {
+    /// Create an empty message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a field; values must be a multiple of 4 bytes long
+    pub fn with(mut self, tag: u32, value: Vec<u8>) -> Self {
+        self.fields.insert(tag, value);
+        self
+    }
+
+    /// Value of a field, if present
+    pub fn get(&self, tag: u32) -> Option<&[u8]> {
+        self.fields.get(&tag).map(Vec::as_slice)
+    }
+
+    /// Value of a field that must be present
+    pub fn require(&self, tag: u32) -> Result<&[u8], TimeServiceError> {
+        self.get(tag).ok_or_else(|| {
+            TimeServiceError::RoughtimeError(format!(
+                "missing {}",
+                String::from_utf8_lossy(&tag.to_le_bytes())
+            ))
+        })
+    }
+
+    /// Fixed-size field that must be present
+    pub fn require_array<const N: usize>(&self, tag: u32) -> Result<[u8; N], TimeServiceError> {
+        self.require(tag)?
+            .try_into()
+            .map_err(|_| malformed("field has the wrong length"))
+    }
+
+    /// Size of the encoded message
+    pub fn encoded_len(&self) -> usize {
+        let n = self.fields.len();
+        4 + 4 * n.saturating_sub(1) + 4 * n + self.fields.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Encode as `count || offsets || tags || values`, all little-endian
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        out.extend_from_slice(&(self.fields.len() as u32).to_le_bytes());
+
+        let mut offset = 0;
+        for value in self
+            .fields
+            .values()
+            .take(self.fields.len().saturating_sub(1))
+        {
+            offset += value.len() as u32;
+            out.extend_from_slice(&offset.to_le_bytes());
+        }
+        for tag in self.fields.keys() {
+            out.extend_from_slice(&tag.to_le_bytes());
+        }
+        for value in self.fields.values() {
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    /// Decode a message, rejecting anything not in canonical form
+    pub fn decode(bytes: &[u8]) -> Result<Self, TimeServiceError> {
+        let word = |index: usize| -> Result<u32, TimeServiceError> {
+            bytes
+                .get(4 * index..4 * index + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| malformed("message is truncated"))
+        };
+        if !bytes.len().is_multiple_of(4) {
+            return Err(malformed("message length is not a multiple of 4"));
+        }
+
+        let count = word(0)? as usize;
+        if count > bytes.len() / 8 {
+            return Err(malformed("too many tags for the message size"));
+        }
+        let header_len = 4 + 8 * count - if count > 0 { 4 } else { 0 };
+        let body = bytes
+            .get(header_len..)
+            .ok_or_else(|| malformed("message is truncated"))?;
+
+        let mut bounds = vec![0];
+        for i in 0..count.saturating_sub(1) {
+            bounds.push(word(1 + i)? as usize);
+        }
+        bounds.push(body.len());
+
+        let mut fields = BTreeMap::new();
+        let mut previous_tag = None;
+        for i in 0..count {
+            let tag = word(count + i)?;
+            if previous_tag.is_some_and(|previous| previous >= tag) {
+                return Err(malformed("tags are not in ascending order"));
+            }
+            previous_tag = Some(tag);
+
+            let (start, end) = (bounds[i], bounds[i + 1]);
+            if start > end || end > body.len() || !start.is_multiple_of(4) {
+                return Err(malformed("invalid value offset"));
+            }
+            fields.insert(tag, body[start..end].to_vec());
+        }
+        Ok(Self { fields })
+    }
+}
+
+/// A padded request carrying `nonce`
+pub fn request(nonce: &[u8; NONCE_SIZE]) -> Vec<u8> {
+    let unpadded = Message::new()
+        .with(TAG_NONC, nonce.to_vec())
+        .with(TAG_PAD, Vec::new());
+    let padding = MIN_REQUEST_SIZE - unpadded.encoded_len();
+    unpadded.with(TAG_PAD, vec![0; padding]).encode()
+}
+
+/// Nonce for the next request of a chain: commits to the previous response
+pub fn chain_nonce(previous_response: &[u8], blind: &[u8]) -> [u8; NONCE_SIZE] {
+    let mut hasher = Sha512::new();
+    hasher.update(previous_response);
+    hasher.update(blind);
+    let mut nonce = [0; NONCE_SIZE];
+    nonce.copy_from_slice(&hasher.finalize());
+    nonce
+}
+
+fn leaf_hash(nonce: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update([0x00]);
+    hasher.update(nonce);
+    hasher.finalize().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+fn micros(time: DateTime<Utc>) -> u64 {
+    time.timestamp_micros().max(0) as u64
+}
+
+/// A verified Roughtime response
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoughtimeResponse {
+    /// authority that answered
+    pub authority_id: String,
+
+    /// nonce the response answers
+    pub nonce: Vec<u8>,
+
+    /// the authority's time
+    pub midpoint: DateTime<Utc>,
+
+    /// the true time was within this radius of the midpoint
+    pub radius: Duration,
+
+    /// the response as received, for chaining and for showing to third parties
+    pub raw: Vec<u8>,
+}
+
+impl RoughtimeResponse {
+    /// Nonce for a request that must provably come after this response
+    pub fn next_nonce(&self, blind: &[u8]) -> [u8; NONCE_SIZE] {
+        chain_nonce(&self.raw, blind)
+    }
+
+    /// Earliest time the response allows
+    pub fn earliest(&self) -> DateTime<Utc> {
+        self.midpoint - chrono::Duration::from_std(self.radius).unwrap_or_default()
+    }
+
+    /// Latest time the response allows
+    pub fn latest(&self) -> DateTime<Utc> {
+        self.midpoint + chrono::Duration::from_std(self.radius).unwrap_or_default()
+    }
+}
+
+/// What an authority answered in one link of a chained query
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ChainResponse {
+    /// a Roughtime response, as received
+    Roughtime(Vec<u8>),
+
+    /// a timestamp signed by a TSP authority
+    Timestamp(Box<AuthenticTimestamp>),
+}
+
+impl ChainResponse {
+    /// Bytes the next link's nonce commits to: the Roughtime response, or
+    /// the signing payload of the timestamp
+    pub fn commitment(&self) -> Result<Vec<u8>, TimeServiceError> {
+        match self {
+            ChainResponse::Roughtime(raw) => Ok(raw.clone()),
+            ChainResponse::Timestamp(timestamp) => timestamp.signing_payload(),
+        }
+    }
+}
+
+/// One response of a chained query
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChainLink {
+    /// authority that answered
+    pub authority_id: String,
+
+    /// fresh randomness mixed into the request's nonce
+    pub blind: Vec<u8>,
+
+    /// the response as received
+    pub response: ChainResponse,
+}
+
+/// Responses to requests whose nonces each commit to the previous response.
+///
+/// Every response was produced after all the responses before it, so anyone
+/// holding the authorities' public keys can check the order offline.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChainedEvidence {
+    /// links in the order they were obtained
+    pub links: Vec<ChainLink>,
+}
+
+impl ChainedEvidence {
+    /// Nonce for the next link: commits to the last response (the first commits to nothing)
+    pub fn next_nonce(&self, blind: &[u8]) -> Result<[u8; NONCE_SIZE], TimeServiceError> {
+        let previous = match self.links.last() {
+            Some(link) => link.response.commitment()?,
+            None => Vec::new(),
+        };
+        Ok(chain_nonce(&previous, blind))
+    }
+
+    /// Nonce each link's request had to carry, in order
+    pub fn expected_nonces(&self) -> Result<Vec<[u8; NONCE_SIZE]>, TimeServiceError> {
+        let mut previous = Vec::new();
+        self.links
+            .iter()
+            .map(|link| {
+                let nonce = chain_nonce(&previous, &link.blind);
+                previous = link.response.commitment()?;
+                Ok(nonce)
+            })
+            .collect()
+    }
+}
+
+/// A chained nonce as carried in a TSP `TimestampRequest` (lowercase hex)
+pub fn request_nonce(nonce: &[u8; NONCE_SIZE]) -> String {
+    nonce.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// First pair of responses whose times contradict the order they were obtained in
+pub fn find_inconsistency(responses: &[RoughtimeResponse]) -> Option<(usize, usize)> {
+    let intervals: Vec<_> = responses
+        .iter()
+        .map(|response| (response.earliest(), response.latest()))
+        .collect();
+    first_contradiction(&intervals)
+}
+
+/// First pair of chained timestamps whose times contradict the order they were
+/// issued in, allowing for each timestamp's signed accuracy
+pub fn find_timestamp_inconsistency(timestamps: &[AuthenticTimestamp]) -> Option<(usize, usize)> {
+    let intervals: Vec<_> = timestamps
+        .iter()
+        .map(|ts| ts.interval().unwrap_or((ts.timestamp, ts.timestamp)))
+        .collect();
+    first_contradiction(&intervals)
+}
+
+fn first_contradiction(intervals: &[(DateTime<Utc>, DateTime<Utc>)]) -> Option<(usize, usize)> {
+    (1..intervals.len()).find_map(|later| {
+        (0..later)
+            .find(|&earlier| intervals[later].1 < intervals[earlier].0)
+            .map(|earlier| (earlier, later))
+    })
+}
+
+/// Check a response against `nonce` using the authority's long-term key
+pub fn verify_response(
+    long_term_key: &PublicKey,
+    authority_id: &str,
+    nonce: &[u8],
+    response: &[u8],
+) -> Result<RoughtimeResponse, TimeServiceError> {
+    let message = Message::decode(response)?;
+    let signed_response = message.require(TAG_SREP)?;
+
+    // the long-term key vouches for the online key
+    let certificate = Message::decode(message.require(TAG_CERT)?)?;
+    let delegation_bytes = certificate.require(TAG_DELE)?;
+    let delegation_signature = Signature::from_bytes(certificate.require(TAG_SIG)?)
+        .map_err(|_| TimeServiceError::InvalidSignature)?;
+    let mut signed = DELEGATION_CONTEXT.to_vec();
+    signed.extend_from_slice(delegation_bytes);
+    long_term_key
+        .verify(&signed, &delegation_signature)
+        .map_err(|_| TimeServiceError::InvalidSignature)?;
+
+    // the online key signed the time
+    let delegation = Message::decode(delegation_bytes)?;
+    let online_key = PublicKey::from_bytes(delegation.require(TAG_PUBK)?)
+        .map_err(|e| TimeServiceError::KeyError(e.to_string()))?;
+    let signature = Signature::from_bytes(message.require(TAG_SIG)?)
+        .map_err(|_| TimeServiceError::InvalidSignature)?;
+    let mut signed = RESPONSE_CONTEXT.to_vec();
+    signed.extend_from_slice(signed_response);
+    online_key
+        .verify(&signed, &signature)
+        .map_err(|_| TimeServiceError::InvalidSignature)?;
+
+    // and the nonce is a leaf of the signed tree
+    let signed_response = Message::decode(signed_response)?;
+    let path = message.require(TAG_PATH)?;
+    if !path.len().is_multiple_of(64) {
+        return Err(malformed("PATH is not a list of SHA-512 hashes"));
+    }
+    let mut index = u32::from_le_bytes(message.require_array(TAG_INDX)?);
+    let mut hash = leaf_hash(nonce);
+    for sibling in path.chunks(64) {
+        hash = match index & 1 {
+            0 => node_hash(&hash, sibling),
+            _ => node_hash(sibling, &hash),
+        };
+        index >>= 1;
+    }
+    if hash != signed_response.require(TAG_ROOT)? {
+        return Err(TimeServiceError::InvalidProof(
+            "nonce is not in the signed tree".to_string(),
+        ));
+    }
+
+    let midpoint = u64::from_le_bytes(signed_response.require_array(TAG_MIDP)?);
+    let radius = u32::from_le_bytes(signed_response.require_array(TAG_RADI)?);
+    let min = u64::from_le_bytes(delegation.require_array(TAG_MINT)?);
+    let max = u64::from_le_bytes(delegation.require_array(TAG_MAXT)?);
+    if midpoint < min || midpoint > max {
+        return Err(TimeServiceError::KeyNotValidAt(authority_id.to_string()));
+    }
+
+    Ok(RoughtimeResponse {
+        authority_id: authority_id.to_string(),
+        nonce: nonce.to_vec(),
+        midpoint: Utc
+            .timestamp_micros(midpoint as i64)
+            .single()
+            .ok_or_else(|| malformed("MIDP out of range"))?,
+        radius: Duration::from_micros(u64::from(radius)),
+        raw: response.to_vec(),
+    })
+}
+
+/// Send a request to a Roughtime server over UDP and return the raw response
+pub async fn query(
+    addr: &str,
+    request: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, TimeServiceError> {
+    let target = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| TimeServiceError::TransportError(format!("cannot resolve {}", addr)))?;
+    // bind in the target's address family, or the send fails
+    let local = if target.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(target).await?;
+    socket.send(request).await?;
+
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    let len = tokio::time::timeout(timeout, socket.recv(&mut buf))
+        .await
+        .map_err(|_| {
+            TimeServiceError::TransportError(format!("no Roughtime response from {}", addr))
+        })??;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// An online key and the certificate delegating to it
+struct OnlineKey {
+    keypair: Keypair,
+    certificate: Vec<u8>,
+    valid_until: DateTime<Utc>,
+}
+
+/// Answers Roughtime requests for a service's authority.
+///
+/// Responses are signed with an online key that the authority's long-term
+/// key delegates to, and a fresh one is delegated before the last expires.
+/// While the authority's clock is not trusted, requests go unanswered, since
+/// Roughtime has no error responses.
+pub struct RoughtimeServer {
+    service: Arc<TspTimeService>,
+    online: Mutex<Option<OnlineKey>>,
+}
+
+impl RoughtimeServer {
+    /// Serve Roughtime for the authority of `service`
+    pub fn new(service: Arc<TspTimeService>) -> Result<Self, TimeServiceError> {
+        if service.get_authority().is_none() {
+            return Err(TimeServiceError::generic("Not configured as an authority"));
+        }
+        Ok(Self {
+            service,
+            online: Mutex::new(None),
+        })
+    }
+
+    /// Answer a single request; None if it is malformed or can't be answered now
+    pub fn respond(&self, request: &[u8]) -> Option<Vec<u8>> {
+        if request.len() < MIN_REQUEST_SIZE {
+            return None;
+        }
+        let nonce: [u8; NONCE_SIZE] = Message::decode(request)
+            .ok()?
+            .require_array(TAG_NONC)
+            .ok()?;
+
+        let authority = self.service.get_authority()?;
+        let (now, error) = authority.clock_reading()?;
+        let radius = error
+            .unwrap_or(DEFAULT_RADIUS)
+            .max(Duration::from_micros(1));
+
+        // one request per response: the tree is the nonce's leaf alone
+        let signed_response = Message::new()
+            .with(TAG_ROOT, leaf_hash(&nonce))
+            .with(TAG_MIDP, micros(now).to_le_bytes().to_vec())
+            .with(
+                TAG_RADI,
+                (radius.as_micros().min(u128::from(u32::MAX)) as u32)
+                    .to_le_bytes()
+                    .to_vec(),
+            )
+            .encode();
+
+        let mut online = self.online.lock().unwrap();
+        if online.as_ref().is_none_or(|key| key.valid_until <= now) {
+            *online = Some(Self::delegate(authority, now));
+        }
+        let online = online.as_ref()?;
+
+        let mut signed = RESPONSE_CONTEXT.to_vec();
+        signed.extend_from_slice(&signed_response);
+        let signature = online.keypair.sign(&signed);
+
+        Some(
+            Message::new()
+                .with(TAG_SIG, signature.to_bytes().to_vec())
+                .with(TAG_PATH, Vec::new())
+                .with(TAG_SREP, signed_response)
+                .with(TAG_CERT, online.certificate.clone())
+                .with(TAG_INDX, 0u32.to_le_bytes().to_vec())
+                .encode(),
+        )
+    }
+
+    // generate an online key valid from `now` and have the long-term key sign for it
+    fn delegate(authority: &TimeAuthorityImpl, now: DateTime<Utc>) -> OnlineKey {
+        let keypair = Keypair::generate(&mut OsRng {});
+        let valid_until = now + chrono::Duration::from_std(DELEGATION_VALIDITY).unwrap_or_default();
+        let delegation = Message::new()
+            .with(TAG_MINT, micros(now).to_le_bytes().to_vec())
+            .with(TAG_MAXT, micros(valid_until).to_le_bytes().to_vec())
+            .with(TAG_PUBK, keypair.public.to_bytes().to_vec())
+            .encode();
+        let certificate = Message::new()
+            .with(TAG_SIG, authority.sign_roughtime_delegation(&delegation))
+            .with(TAG_DELE, delegation)
+            .encode();
+
+        OnlineKey {
+            keypair,
+            certificate,
+            valid_until,
+        }
+    }
+}
+
+/// Serve Roughtime over UDP until the socket fails
+pub async fn serve(socket: UdpSocket, server: Arc<RoughtimeServer>) -> io::Result<()> {
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        if let Some(response) = server.respond(&buf[..len]) {
+            if let Err(e) = socket.send_to(&response, peer).await {
+                eprintln!("Roughtime send error: {}", e);
+            }
+        }
+    }
+}
+
+/// Fresh random bytes for a nonce or a chaining blind
+pub fn random_nonce() -> [u8; NONCE_SIZE] {
+    let mut nonce = [0; NONCE_SIZE];
+    OsRng {}.fill_bytes(&mut nonce);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_layout() {
+        let message = Message::new()
+            .with(TAG_PAD, vec![0; 4])
+            .with(TAG_NONC, vec![7; 8]);
+        let encoded = message.encode();
+
+        // count, one offset, then tags in ascending numeric order
+        assert_eq!(&encoded[..4], &2u32.to_le_bytes());
+        assert_eq!(&encoded[4..8], &8u32.to_le_bytes());
+        assert_eq!(&encoded[8..12], b"NONC");
+        assert_eq!(&encoded[12..16], b"PAD\xff");
+        assert_eq!(Message::decode(&encoded).unwrap(), message);
+        assert_eq!(request(&[0; NONCE_SIZE]).len(), MIN_REQUEST_SIZE);
+
+        // out-of-order tags are rejected
+        let mut swapped = encoded.clone();
+        swapped[8..16].copy_from_slice(b"PAD\xffNONC");
+        assert!(Message::decode(&swapped).is_err());
+    }
+
+    // start a Roughtime server on loopback, returning it, its address and its public key
+    async fn start_server(authority_id: &str) -> (Arc<RoughtimeServer>, String, Vec<u8>) {
+        let mut service = TspTimeService::new();
+        service.as_authority(authority_id.to_string());
+        let public_key = service.get_authority_public_key().unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let server = Arc::new(RoughtimeServer::new(Arc::new(service)).unwrap());
+        tokio::spawn(serve(socket, server.clone()));
+        (server, addr, public_key)
+    }
+
+    #[tokio::test]
+    async fn test_udp_query_and_chained_nonce() {
+        let (server, addr, public_key) = start_server("roughtime.authority").await;
+        let mut client = crate::TimeClient::new_anonymous();
+        client
+            .add_authority("roughtime.authority".to_string(), &public_key)
+            .unwrap();
+
+        let first = client
+            .request_roughtime("roughtime.authority", &addr, &random_nonce())
+            .await
+            .unwrap();
+        assert!((Utc::now() - first.midpoint).num_seconds().abs() < 5);
+
+        // the second nonce commits to the first response
+        let blind = random_nonce();
+        let second = client
+            .request_roughtime("roughtime.authority", &addr, &first.next_nonce(&blind))
+            .await
+            .unwrap();
+        assert_eq!(second.nonce, chain_nonce(&first.raw, &blind).to_vec());
+
+        // the response doesn't verify for any other nonce, and short requests are ignored
+        assert!(client
+            .verify_roughtime_response("roughtime.authority", &first.nonce, &second.raw)
+            .is_err());
+        assert!(server.respond(&[0; 64]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_udp_query_over_ipv6() {
+        // skip where the host has no IPv6 loopback
+        let Ok(socket) = UdpSocket::bind("[::1]:0").await else {
+            return;
+        };
+        let addr = socket.local_addr().unwrap().to_string();
+        let mut service = TspTimeService::new();
+        service.as_authority("roughtime.authority".to_string());
+        let public_key = service.get_authority_public_key().unwrap();
+        let server = Arc::new(RoughtimeServer::new(Arc::new(service)).unwrap());
+        tokio::spawn(serve(socket, server));
+
+        let mut client = crate::TimeClient::new_anonymous();
+        client
+            .add_authority("roughtime.authority".to_string(), &public_key)
+            .unwrap();
+        let response = client
+            .request_roughtime("roughtime.authority", &addr, &random_nonce())
+            .await
+            .unwrap();
+        assert!((Utc::now() - response.midpoint).num_seconds().abs() < 5);
+    }
+
+    #[tokio::test]
+    async fn test_chained_evidence_verifies_offline_in_order() {
+        let (_, addr_a, key_a) = start_server("authority.a").await;
+        let (_, addr_b, key_b) = start_server("authority.b").await;
+        let mut client = crate::TimeClient::new_anonymous();
+        client
+            .add_authority("authority.a".to_string(), &key_a)
+            .unwrap();
+        client
+            .add_authority("authority.b".to_string(), &key_b)
+            .unwrap();
+
+        let evidence = client
+            .request_roughtime_chain(&[("authority.a", &addr_a), ("authority.b", &addr_b)])
+            .await
+            .unwrap();
+
+        // a third party re-verifies the bundle without contacting anyone
+        let json = serde_json::to_string(&evidence).unwrap();
+        let mut verifier = crate::TimeClient::new_anonymous();
+        verifier
+            .add_authority("authority.a".to_string(), &key_a)
+            .unwrap();
+        verifier
+            .add_authority("authority.b".to_string(), &key_b)
+            .unwrap();
+        let responses = verifier
+            .verify_roughtime_chain(&serde_json::from_str(&json).unwrap())
+            .unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[1].authority_id, "authority.b");
+        assert_eq!(find_inconsistency(&responses), None);
+
+        // presenting B's answer as coming before A's breaks the chain
+        let mut reordered = evidence.clone();
+        reordered.links.reverse();
+        assert!(verifier.verify_roughtime_chain(&reordered).is_err());
+
+        // a response earlier than one obtained before it is reported
+        let mut skewed = responses.clone();
+        skewed[1].midpoint = skewed[0].earliest() - chrono::Duration::seconds(10);
+        assert_eq!(find_inconsistency(&skewed), Some((0, 1)));
+    }
+}
@@ -0,0 +1,207 @@
+// Strictly increasing serial numbers and non-decreasing issue times
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+use crate::error::TimeServiceError;
+
+/// Serials reserved on disk at a time
+const SERIAL_BLOCK: u64 = 1_000;
+
+/// How far past the current time a reservation covers issue times
+const TIME_RESERVATION_SECS: i64 = 1;
+
+/// What is persisted: everything at or above these bounds is still unused
+#[derive(Serialize, Deserialize)]
+struct Reservation {
+    /// first serial not yet handed out by a previous run
+    next_serial: u64,
+
+    /// no earlier run issued a time later than this
+    time_floor: DateTime<Utc>,
+}
+
+struct CounterState {
+    next_serial: u64,
+    last_time: DateTime<Utc>,
+
+    /// serials below this are covered by the persisted reservation
+    serial_limit: u64,
+
+    /// issue times up to this are covered by the persisted reservation
+    time_ceiling: DateTime<Utc>,
+}
+
+/// Hands out serial numbers with times that never go backwards.
+///
+/// When backed by a file, serials and times are reserved in blocks so that a
+/// restarted authority continues above anything the previous run issued
+/// without writing to disk for every timestamp. Reservations are written on
+/// the blocking pool; callers taking a serial meanwhile wait their turn.
+pub struct SerialCounter {
+    path: Option<PathBuf>,
+    state: Mutex<CounterState>,
+}
+
+impl Default for SerialCounter {
+    fn default() -> Self {
+        Self {
+            path: None,
+            state: Mutex::new(CounterState {
+                next_serial: 1,
+                last_time: DateTime::<Utc>::MIN_UTC,
+                serial_limit: u64::MAX,
+                time_ceiling: DateTime::<Utc>::MAX_UTC,
+            }),
+        }
+    }
+}
+
+impl SerialCounter {
+    /// Create a counter starting at serial 1 that is not persisted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open (or create) a counter persisted at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TimeServiceError> {
+        let path = path.as_ref().to_path_buf();
+        let reservation = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Reservation {
+                next_serial: 1,
+                time_floor: DateTime::<Utc>::MIN_UTC,
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(CounterState {
+                next_serial: reservation.next_serial,
+                last_time: reservation.time_floor,
+                // nothing is reserved yet; the first issuance reserves a block
+                serial_limit: reservation.next_serial,
+                time_ceiling: reservation.time_floor,
+            }),
+        })
+    }
+
+    /// Serial the next timestamp will get
+    pub async fn peek(&self) -> u64 {
+        self.state.lock().await.next_serial
+    }
+
+    /// Take the next serial and the time to issue it at.
+    ///
+    /// The time is `clock_time`, or the previous issue time if the clock has
+    /// stepped back behind it.
+    pub async fn next(
+        &self,
+        clock_time: DateTime<Utc>,
+    ) -> Result<(u64, DateTime<Utc>), TimeServiceError> {
+        let mut state = self.state.lock().await;
+        let serial = state.next_serial;
+        let time = clock_time.max(state.last_time);
+
+        if serial >= state.serial_limit || time > state.time_ceiling {
+            self.reserve(&mut state, serial, time).await?;
+        }
+
+        state.next_serial = serial
+            .checked_add(1)
+            .ok_or_else(|| TimeServiceError::generic("serial numbers exhausted"))?;
+        state.last_time = time;
+        Ok((serial, time))
+    }
+
+    /// Persist a reservation covering `serial` and `time`
+    async fn reserve(
+        &self,
+        state: &mut CounterState,
+        serial: u64,
+        time: DateTime<Utc>,
+    ) -> Result<(), TimeServiceError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let reservation = Reservation {
+            next_serial: serial.saturating_add(SERIAL_BLOCK),
+            time_floor: time + Duration::seconds(TIME_RESERVATION_SECS),
+        };
+        let (serial_limit, time_ceiling) = (reservation.next_serial, reservation.time_floor);
+
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || write_reservation(&path, &reservation))
+            .await
+            .map_err(|e| {
+                TimeServiceError::generic(format!("serial reservation task failed: {}", e))
+            })??;
+
+        state.serial_limit = serial_limit;
+        state.time_ceiling = time_ceiling;
+        Ok(())
+    }
+}
+
+// write a new file and rename it over the old one so a crash leaves either;
+// the temp file is named after the whole file name, so state.serial and
+// state.nonces don't share one
+fn write_reservation(path: &Path, reservation: &Reservation) -> Result<(), TimeServiceError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(&serde_json::to_vec(reservation)?)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_restart_continues_above_previous_run() {
+        let path = std::env::temp_dir().join(format!("serial-{:x}.json", rand::random::<u64>()));
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        let (last_serial, last_time) = {
+            let counter = SerialCounter::open(&path).unwrap();
+            assert_eq!(counter.next(start).await.unwrap(), (1, start));
+            counter
+                .next(start + Duration::milliseconds(500))
+                .await
+                .unwrap()
+        };
+
+        // after a restart with the clock set back, neither serials nor times go backwards
+        let counter = SerialCounter::open(&path).unwrap();
+        let (serial, time) = counter.next(start - Duration::hours(1)).await.unwrap();
+        assert!(serial > last_serial);
+        assert!(time >= last_time);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reservations_leave_neighbouring_files_alone() {
+        let base = std::env::temp_dir().join(format!("state-{:x}", rand::random::<u64>()));
+        let path = base.with_extension("serial");
+        let neighbour = base.with_extension("tmp");
+        fs::write(&neighbour, b"someone else's").unwrap();
+
+        let counter = SerialCounter::open(&path).unwrap();
+        counter.next(Utc::now()).await.unwrap();
+
+        assert_eq!(fs::read(&neighbour).unwrap(), b"someone else's");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&neighbour).unwrap();
+    }
+}
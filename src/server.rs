@@ -0,0 +1,125 @@
+// HTTP/JSON server exposing a time authority on the network
+use authentic_time_service::batch::DEFAULT_MAX_BATCH_SIZE;
+use authentic_time_service::http::serve;
+use authentic_time_service::roughtime;
+use authentic_time_service::{
+    AuthorityConfig, BatchConfig, KeyFileConfig, KeyFormat, RoughtimeServer, TspTimeService,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, UdpSocket};
+
+const USAGE: &str = "Usage: time_authority_server --id <authority-id> [--addr <host:port>] \
+[--key <path> [--key-format raw|pem|jwk]] [--nonce-file <path>] [--serial-file <path>] [--log-file <path>] [--batch-ms <window>] \
+[--roughtime-addr <host:port>] [--replica-id <id>]
+
+The signing key is created at <path> if it does not exist. Set
+TIME_AUTHORITY_KEY_PASSPHRASE to encrypt it with a passphrase. Served nonces
+are journaled to --nonce-file so replays are rejected across restarts, and
+--serial-file keeps serial numbers increasing across restarts. Issued timestamps
+are hash-chained and appended to --log-file. With --batch-ms, requests arriving
+within the window are signed together under one Merkle root. With
+--roughtime-addr, the authority also answers Roughtime requests over UDP.
+Replicas sharing an id and key must each be given their own --replica-id.";
+
+// Main function to run a time authority server
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut authority_id = None;
+    let mut addr = "127.0.0.1:8080".to_string();
+    let mut key_path = None;
+    let mut key_format = KeyFormat::Pem;
+    let mut nonce_file = None;
+    let mut serial_file = None;
+    let mut log_file = None;
+    let mut batch_window = None;
+    let mut roughtime_addr = None;
+    let mut replica_id = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--id" => authority_id = args.next(),
+            "--addr" => addr = args.next().ok_or(USAGE)?,
+            "--key" => key_path = args.next(),
+            "--nonce-file" => nonce_file = Some(args.next().ok_or(USAGE)?),
+            "--serial-file" => serial_file = Some(args.next().ok_or(USAGE)?),
+            "--log-file" => log_file = Some(args.next().ok_or(USAGE)?),
+            "--roughtime-addr" => roughtime_addr = Some(args.next().ok_or(USAGE)?),
+            "--replica-id" => replica_id = Some(args.next().ok_or(USAGE)?),
+            "--batch-ms" => {
+                let millis: u64 = args.next().ok_or(USAGE)?.parse()?;
+                batch_window = Some(Duration::from_millis(millis));
+            }
+            "--key-format" => {
+                key_format = match args.next().as_deref() {
+                    Some("raw") => KeyFormat::Raw,
+                    Some("pem") => KeyFormat::Pem,
+                    Some("jwk") => KeyFormat::Jwk,
+                    _ => return Err(USAGE.into()),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other => return Err(format!("unknown argument {}\n{}", other, USAGE).into()),
+        }
+    }
+    let authority_id = authority_id.ok_or(USAGE)?;
+
+    let mut config = AuthorityConfig::new(authority_id.clone());
+    if let Some(path) = key_path {
+        config = config.with_key_file(KeyFileConfig {
+            path: path.into(),
+            format: key_format,
+            passphrase: std::env::var("TIME_AUTHORITY_KEY_PASSPHRASE").ok(),
+            create_if_missing: true,
+        });
+    }
+
+    if let Some(path) = nonce_file {
+        config = config.with_nonce_file(path);
+    }
+    if let Some(path) = serial_file {
+        config = config.with_serial_file(path);
+    }
+    if let Some(path) = log_file {
+        config = config.with_log_file(path);
+    }
+    if let Some(window) = batch_window {
+        config = config.with_batching(BatchConfig::new(window, DEFAULT_MAX_BATCH_SIZE));
+    }
+    if let Some(replica_id) = replica_id {
+        config = config.with_replica_id(replica_id);
+    }
+
+    let mut service = TspTimeService::new();
+    service.as_authority_with_config(config)?;
+    let metadata = service
+        .get_authority_metadata()
+        .expect("service is configured as an authority");
+
+    let service = Arc::new(service);
+    if let Some(roughtime_addr) = roughtime_addr {
+        let socket = UdpSocket::bind(&roughtime_addr).await?;
+        println!("Roughtime on udp://{}", socket.local_addr()?);
+        let server = Arc::new(RoughtimeServer::new(service.clone())?);
+        tokio::spawn(async move {
+            if let Err(e) = roughtime::serve(socket, server).await {
+                eprintln!("Roughtime server stopped: {}", e);
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!(
+        "Time authority '{}' listening on http://{}",
+        authority_id,
+        listener.local_addr()?
+    );
+    println!("Public key: {:?}", metadata.public_key);
+
+    serve(listener, service).await?;
+    Ok(())
+}
@@ -1,33 +1,281 @@
 // High-level service implementation that integrates with TSP
 
 use async_trait::async_trait;
-use ed25519_dalek::{PublicKey, Signature};
+use ed25519_dalek::Keypair;
+use rand::rngs::OsRng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::authority::{TimeAuthority, TimeAuthorityImpl};
+use crate::batch::BatchConfig;
+use crate::chain::{ConsistencyRequest, InclusionRequest, LogRange, TimestampLog};
 use crate::client::TimeClient;
+use crate::clock::{ClockSource, SystemClock};
+use crate::consensus::TimeSample;
 use crate::error::TimeServiceError;
-use crate::models::{AuthenticTimestamp, TimestampStatus};
+use crate::health::ClockSync;
+use crate::keys::KeyFormat;
+use crate::merkle::{ConsistencyProof, InclusionProof};
+use crate::models::{
+    AuthenticTimestamp, AuthorityMetadata, KeyValidity, SignedTreeHead, TimestampRequest,
+    TimestampResponse, TimestampStatus,
+};
+use crate::nonce::FileNonceStore;
+use crate::pending::TrackedResponse;
+use crate::policy::{Verdict, VerificationPolicy};
+use crate::quorum::{QuorumFailure, QuorumTimestamp};
+use crate::ratelimit::RateLimitConfig;
+use crate::roughtime::{self, ChainLink, ChainResponse, ChainedEvidence};
+use crate::serial::SerialCounter;
+
+/// method name for timestamp requests
+pub const METHOD_TIMESTAMP_REQUEST: &str = "time/request";
+
+/// method name for fetching the authority's public key
+pub const METHOD_PUBLIC_KEY: &str = "time/public_key";
+
+/// method name for fetching the authority's metadata
+pub const METHOD_METADATA: &str = "time/metadata";
+
+/// method name for fetching a range of the authority's timestamp log
+pub const METHOD_LOG_RANGE: &str = "time/log_range";
+
+/// method name for fetching the authority's signed tree head
+pub const METHOD_TREE_HEAD: &str = "time/tree_head";
+
+/// method name for fetching an inclusion proof from the authority's log
+pub const METHOD_INCLUSION_PROOF: &str = "time/inclusion_proof";
+
+/// method name for fetching a consistency proof between two tree heads
+pub const METHOD_CONSISTENCY_PROOF: &str = "time/consistency_proof";
+
+/// every method handled by `TspTimeService::handle_envelope`
+pub const METHODS: &[&str] = &[
+    METHOD_TIMESTAMP_REQUEST,
+    METHOD_PUBLIC_KEY,
+    METHOD_METADATA,
+    METHOD_LOG_RANGE,
+    METHOD_TREE_HEAD,
+    METHOD_INCLUSION_PROOF,
+    METHOD_CONSISTENCY_PROOF,
+];
+
+/// serialized request sent to a remote endpoint over TSP
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RequestEnvelope {
+    /// method being invoked on the remote endpoint
+    pub method: String,
+
+    /// JSON-encoded request body
+    pub payload: Vec<u8>,
+
+    /// network address the envelope was received from, set by the receiving server
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+/// serialized response returned by a remote endpoint over TSP
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResponseEnvelope {
+    /// JSON-encoded response body (empty on error)
+    pub payload: Vec<u8>,
+
+    /// error reported by the remote endpoint, if any
+    pub error: Option<String>,
+}
+
+impl RequestEnvelope {
+    /// Serialize a request body into an envelope for the given method
+    pub fn new<T: Serialize>(method: &str, request: &T) -> Result<Self, TimeServiceError> {
+        Ok(Self {
+            method: method.to_string(),
+            payload: serde_json::to_vec(request)?,
+            source: None,
+        })
+    }
+
+    /// Deserialize the request body
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, TimeServiceError> {
+        Ok(serde_json::from_slice(&self.payload)?)
+    }
+}
+
+impl ResponseEnvelope {
+    /// Serialize a successful response body
+    pub fn ok<T: Serialize>(response: &T) -> Result<Self, TimeServiceError> {
+        Ok(Self {
+            payload: serde_json::to_vec(response)?,
+            error: None,
+        })
+    }
+
+    /// Build an error response
+    pub fn error<T: fmt::Display>(message: T) -> Self {
+        Self {
+            payload: vec![],
+            error: Some(message.to_string()),
+        }
+    }
+
+    /// Deserialize the response body, surfacing remote errors
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, TimeServiceError> {
+        if let Some(error) = &self.error {
+            return Err(TimeServiceError::RequestRejected(error.clone()));
+        }
+        Ok(serde_json::from_slice(&self.payload)?)
+    }
+}
 
 /// trait for TSP communication - would be implemented by actual TSP client
 #[async_trait]
 pub trait TspCommunication: Send + Sync {
-    /// Send a request to a remote endpoint and get a response
-    async fn send_request<T, R>(
+    /// Send a serialized request to a remote endpoint and get its serialized response
+    async fn send_envelope(
+        &self,
+        endpoint: &str,
+        request: RequestEnvelope,
+    ) -> Result<ResponseEnvelope, TimeServiceError>;
+}
+
+impl dyn TspCommunication + '_ {
+    /// Send a typed request to a remote endpoint and decode the typed response
+    pub async fn send_request<T, R>(
         &self,
         endpoint: &str,
         method: &str,
         request: &T,
     ) -> Result<R, TimeServiceError>
     where
-        T: serde::Serialize + Send + Sync,
-        R: for<'de> serde::Deserialize<'de> + Send;
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let envelope = RequestEnvelope::new(method, request)?;
+        self.send_envelope(endpoint, envelope).await?.decode()
+    }
+}
+
+/// where a time authority's signing key is persisted
+#[derive(Clone, Debug)]
+pub struct KeyFileConfig {
+    /// path of the key file
+    pub path: PathBuf,
+
+    /// encoding of the key file
+    pub format: KeyFormat,
+
+    /// passphrase protecting the key file, if it is encrypted
+    pub passphrase: Option<String>,
+
+    /// generate and save a new key if the file does not exist yet
+    pub create_if_missing: bool,
+}
+
+/// configuration for running a service as a time authority
+#[derive(Clone, Debug)]
+pub struct AuthorityConfig {
+    /// identifier of the authority
+    pub id: String,
+
+    /// persistent signing key; a fresh key is generated when absent
+    pub key_file: Option<KeyFileConfig>,
+
+    /// request quotas (unlimited by default)
+    pub rate_limits: RateLimitConfig,
+
+    /// journal of served nonces kept across restarts; kept in memory when absent
+    pub nonce_file: Option<PathBuf>,
+
+    /// refuse to sign while the clock error bound exceeds this (None always signs)
+    pub max_clock_error: Option<Duration>,
+
+    /// file reserving serial numbers so they keep increasing across restarts
+    pub serial_file: Option<PathBuf>,
+
+    /// file the hash-chained timestamp log is appended to; kept in memory when absent
+    pub log_file: Option<PathBuf>,
+
+    /// sign requests in batches under one signature (None signs each request)
+    pub batching: Option<BatchConfig>,
+
+    /// which replica of the authority this is, when several share its id and key
+    pub replica_id: Option<String>,
+}
+
+impl AuthorityConfig {
+    /// Create a configuration with an ephemeral signing key
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            key_file: None,
+            rate_limits: RateLimitConfig::default(),
+            nonce_file: None,
+            max_clock_error: None,
+            serial_file: None,
+            log_file: None,
+            batching: None,
+            replica_id: None,
+        }
+    }
+
+    /// Persist the signing key in the given key file
+    pub fn with_key_file(mut self, key_file: KeyFileConfig) -> Self {
+        self.key_file = Some(key_file);
+        self
+    }
+
+    /// Limit how often clients may request timestamps
+    pub fn with_rate_limits(mut self, rate_limits: RateLimitConfig) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Remember served nonces in a journal file so replays are rejected across restarts
+    pub fn with_nonce_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.nonce_file = Some(path.into());
+        self
+    }
+
+    /// Persist serial numbers so a restarted authority continues above them
+    pub fn with_serial_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.serial_file = Some(path.into());
+        self
+    }
+
+    /// Append issued timestamps to a log file so the chain continues across restarts
+    pub fn with_log_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Collect requests into batches and sign only each batch's Merkle root
+    pub fn with_batching(mut self, batching: BatchConfig) -> Self {
+        self.batching = Some(batching);
+        self
+    }
+
+    /// Run as one named replica of an authority whose id and key are shared,
+    /// so each replica's serials and log are told apart
+    pub fn with_replica_id(mut self, replica_id: String) -> Self {
+        self.replica_id = Some(replica_id);
+        self
+    }
+
+    /// Only sign while the clock is synchronized to within `limit`
+    pub fn with_max_clock_error(mut self, limit: Duration) -> Self {
+        self.max_clock_error = Some(limit);
+        self
+    }
 }
 
 /// an example time service implementation that builds on top of the tsp
 pub struct TspTimeService {
-    // Reference to the underlying TSP implementation (would be provided in real implementation)
-    // tsp_client: Box<dyn TspCommunication>,
+    // Reference to the underlying TSP implementation, if remote authorities are used
+    tsp_client: Option<Box<dyn TspCommunication>>,
 
     // Our time authority implementation
     authority: Option<TimeAuthorityImpl>,
@@ -37,28 +285,106 @@ pub struct TspTimeService {
 
     // Cache of authority endpoints (ID -> endpoint mapping)
     authority_endpoints: HashMap<String, String>,
+
+    // Clock shared by the authority and the client
+    clock: Arc<dyn ClockSource>,
+}
+
+impl Default for TspTimeService {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TspTimeService {
     // Create a new time service
     pub fn new() -> Self {
         Self {
-            // tsp_client: Box::new(TspClient::new()),
+            tsp_client: None,
             authority: None,
             client: TimeClient::new_anonymous(),
             authority_endpoints: HashMap::new(),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    // set the TSP transport used to reach remote authorities
+    pub fn set_transport(&mut self, transport: Box<dyn TspCommunication>) {
+        self.tsp_client = Some(transport);
+    }
+
     // configure this service as a time authority
     pub fn as_authority(&mut self, id: String) {
-        println!("DEBUG: Setting up authority with ID: {}", id);
-        self.authority = Some(TimeAuthorityImpl::new(id));
+        self.authority = Some(TimeAuthorityImpl::with_clock(id, self.clock.clone()));
+        self.trust_local_authority();
+    }
+
+    // configure this service as a time authority from a full configuration
+    pub fn as_authority_with_config(
+        &mut self,
+        config: AuthorityConfig,
+    ) -> Result<(), TimeServiceError> {
+        let mut authority = match &config.key_file {
+            None => TimeAuthorityImpl::with_clock(config.id, self.clock.clone()),
+            Some(key_file) => {
+                let passphrase = key_file.passphrase.as_deref();
+                if key_file.create_if_missing && !key_file.path.exists() {
+                    let authority = TimeAuthorityImpl::with_clock(config.id, self.clock.clone());
+                    authority.save_key_file(&key_file.path, key_file.format, passphrase)?;
+                    authority
+                } else {
+                    TimeAuthorityImpl::from_key_file(
+                        config.id,
+                        &key_file.path,
+                        key_file.format,
+                        passphrase,
+                    )?
+                }
+            }
+        };
+
+        authority.set_rate_limits(config.rate_limits);
+        if let Some(path) = &config.nonce_file {
+            authority.set_nonce_store(Arc::new(FileNonceStore::open(path)?));
+        }
+        if let Some(path) = &config.serial_file {
+            authority.set_serial_counter(SerialCounter::open(path)?);
+        }
+        if let Some(path) = &config.log_file {
+            authority.set_log(TimestampLog::open(path)?);
+        }
+        authority.set_batching(config.batching);
+        authority.set_replica_id(config.replica_id);
+        authority.set_clock(self.clock.clone());
+        authority.set_max_clock_error(config.max_clock_error);
+        self.authority = Some(authority);
+        self.trust_local_authority();
+        Ok(())
     }
 
     // configure this service as an authenticated client
     pub fn as_authenticated_client(&mut self, id: String) {
         self.client = TimeClient::new_authenticated(id);
+        self.client.set_clock(self.clock.clone());
+        self.trust_local_authority();
+    }
+
+    // let our client verify what our own authority signs, like any other authority's timestamps
+    fn trust_local_authority(&mut self) {
+        if let Some(authority) = &self.authority {
+            self.client
+                .add_authority_metadata(&authority.metadata())
+                .expect("an authority's own keys are valid");
+        }
+    }
+
+    // read time from the given clock, both as an authority and as a client
+    pub fn set_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        if let Some(authority) = &mut self.authority {
+            authority.set_clock(clock.clone());
+        }
+        self.client.set_clock(clock.clone());
+        self.clock = clock;
     }
 
     // add an authority's public key for verification
@@ -70,16 +396,48 @@ impl TspTimeService {
         self.client.add_authority(authority_id, public_key)
     }
 
+    // trust every key published in an authority's metadata
+    pub fn add_authority_metadata(
+        &mut self,
+        metadata: &AuthorityMetadata,
+    ) -> Result<(), TimeServiceError> {
+        self.client.add_authority_metadata(metadata)
+    }
+
+    // rotate this service's authority to a freshly generated signing key
+    pub fn rotate_authority_key(
+        &mut self,
+        overlap: Duration,
+    ) -> Result<KeyValidity, TimeServiceError> {
+        let authority = self
+            .authority
+            .as_mut()
+            .ok_or_else(|| TimeServiceError::generic("Not configured as an authority"))?;
+        let key = authority.rotate_key(Keypair::generate(&mut OsRng {}), overlap);
+        self.trust_local_authority();
+        Ok(key)
+    }
+
     // add an authority endpoint mapping
     pub fn add_authority_endpoint(&mut self, authority_id: String, endpoint: String) {
         self.authority_endpoints.insert(authority_id, endpoint);
     }
 
+    // get our authority (if we're an authority)
+    pub fn get_authority(&self) -> Option<&TimeAuthorityImpl> {
+        self.authority.as_ref()
+    }
+
     // Get the public key of this service's authority (if configured as an authority)
     pub fn get_authority_public_key(&self) -> Option<Vec<u8>> {
         self.authority.as_ref().map(|auth| auth.get_public_key())
     }
 
+    // get the metadata of this service's authority (if configured as an authority)
+    pub fn get_authority_metadata(&self) -> Option<AuthorityMetadata> {
+        self.authority.as_ref().map(|auth| auth.metadata())
+    }
+
     // get the client's public key (if configured as an authenticated client)
     pub fn get_client_public_key(&self) -> Option<Vec<u8>> {
         self.client.get_public_key()
@@ -90,64 +448,288 @@ impl TspTimeService {
         &self,
         authority_id: &str,
     ) -> Result<AuthenticTimestamp, TimeServiceError> {
-        println!(
-            "DEBUG: Requesting timestamp from authority: {}",
-            authority_id
-        );
-
         let request = self.client.create_request();
+        let response = self.send_timestamp_request(authority_id, request).await?;
+        Ok(response.timestamp)
+    }
+
+    // request a timestamp vouching for the given data from a remote authority
+    pub async fn request_data_timestamp(
+        &self,
+        authority_id: &str,
+        data: &[u8],
+    ) -> Result<AuthenticTimestamp, TimeServiceError> {
+        let request = self.client.create_request_for_data(data);
+        let response = self.send_timestamp_request(authority_id, request).await?;
+        Ok(response.timestamp)
+    }
+
+    // request timestamps from authorities in turn, deriving each request's nonce
+    // from the previous timestamp and a fresh blind, so the evidence proves the
+    // order they were issued in
+    pub async fn request_timestamp_chain(
+        &self,
+        authority_ids: &[String],
+    ) -> Result<ChainedEvidence, TimeServiceError> {
+        let mut evidence = ChainedEvidence::default();
+        for authority_id in authority_ids {
+            let blind = roughtime::random_nonce();
+            let nonce = roughtime::request_nonce(&evidence.next_nonce(&blind)?);
+            let request = self.client.create_request_with_nonce(nonce);
+            let response = self.send_timestamp_request(authority_id, request).await?;
+            evidence.links.push(ChainLink {
+                authority_id: authority_id.clone(),
+                blind: blind.to_vec(),
+                response: ChainResponse::Timestamp(Box::new(response.timestamp)),
+            });
+        }
+        Ok(evidence)
+    }
 
-        // In a real implementation, we would look up the authority endpoint
-        // and use the TSP client to send the request
-        /*
-        if let Some(endpoint) = self.authority_endpoints.get(authority_id) {
-            let response = self.tsp_client
-                .send_request(endpoint, "time/request", &request)
-                .await?;
-
-            if response.status == TimestampStatus::Success {
-                return Ok(response.timestamp);
-            } else {
-                return Err(TimeServiceError::RequestRejected(format!("{:?}", response.status)));
+    // verify chained timestamps offline, returning them in the order they were issued
+    pub fn verify_timestamp_chain(
+        &self,
+        evidence: &ChainedEvidence,
+    ) -> Result<Vec<AuthenticTimestamp>, TimeServiceError> {
+        self.client.verify_timestamp_chain(evidence)
+    }
+
+    // request timestamps from several authorities concurrently and require
+    // at least `threshold` of them to return valid signed responses
+    pub async fn request_quorum_timestamp(
+        &self,
+        authority_ids: &[String],
+        threshold: usize,
+        data: Option<&[u8]>,
+    ) -> Result<QuorumTimestamp, TimeServiceError> {
+        let requests = authority_ids.iter().map(|authority_id| {
+            let request = match data {
+                Some(data) => self.client.create_request_for_data(data),
+                None => self.client.create_request(),
+            };
+            self.send_timestamp_request(authority_id, request)
+        });
+        let responses = futures::future::join_all(requests).await;
+
+        // project every sample to the moment the last response arrived
+        let reference = responses
+            .iter()
+            .filter_map(|response| response.as_ref().ok())
+            .map(|response| response.received_at)
+            .max()
+            .unwrap_or_else(Instant::now);
+
+        let mut timestamps = Vec::new();
+        let mut samples = Vec::new();
+        let mut failures = Vec::new();
+        for (authority_id, response) in authority_ids.iter().zip(responses) {
+            // responses were verified as they were accepted
+            match response {
+                Ok(response) => {
+                    let timestamp = response.timestamp;
+                    samples.push(TimeSample {
+                        authority_id: timestamp.authority_id.clone(),
+                        time: timestamp.timestamp,
+                        // +1 covers the sub-microsecond truncation of rtt and age
+                        rtt_micros: response.rtt.as_micros() as u64 + 1,
+                        age_micros: (reference - response.received_at).as_micros() as u64,
+                        accuracy_micros: timestamp
+                            .accuracy
+                            .map_or(0, |accuracy| accuracy.as_micros()),
+                    });
+                    timestamps.push(timestamp);
+                }
+                Err(e) => failures.push(QuorumFailure {
+                    authority_id: authority_id.clone(),
+                    reason: e.to_string(),
+                }),
             }
         }
-        */
 
-        // for demonstration purposes, we'll simulate the request locally if we have an authority
+        QuorumTimestamp::new(timestamps, samples, threshold, failures)
+    }
+
+    // measure our clock against the consensus of peer authorities and record
+    // the result in the local authority's clock health
+    pub async fn synchronize_clock(
+        &self,
+        authority_ids: &[String],
+        threshold: usize,
+    ) -> Result<ClockSync, TimeServiceError> {
+        let started = Instant::now();
+        let quorum = self
+            .request_quorum_timestamp(authority_ids, threshold, None)
+            .await?;
+        let local = self.clock.now();
+
+        // the consensus holds at the last response, which is no earlier than `started`,
+        // so the true time now is within [earliest, latest + elapsed]
+        let elapsed = chrono::Duration::from_std(started.elapsed())
+            .map_err(|e| TimeServiceError::generic(e.to_string()))?;
+        let earliest = quorum.consensus.earliest;
+        let latest = quorum.consensus.latest + elapsed;
+        let half_width = (latest - earliest) / 2;
+
+        let sync = ClockSync {
+            offset: (earliest + half_width) - local,
+            uncertainty: half_width.to_std().unwrap_or(Duration::ZERO),
+            at: local,
+        };
         if let Some(authority) = &self.authority {
-            // Check if this is a request for our local authority
-            let auth_id = authority.get_id();
-            println!("DEBUG: Local authority ID: {}", auth_id);
-            println!("DEBUG: Requested authority ID: {}", authority_id);
-
-            if auth_id == authority_id {
-                println!("DEBUG: IDs match, issuing timestamp");
-                let response = authority.issue_timestamp(request).await?;
-
-                if response.status == TimestampStatus::Success {
-                    println!("DEBUG: Timestamp issued successfully");
-                    return Ok(response.timestamp);
-                } else {
-                    println!("DEBUG: Request rejected: {:?}", response.status);
-                    return Err(TimeServiceError::RequestRejected(format!(
-                        "{:?}",
-                        response.status
-                    )));
-                }
-            } else {
-                println!(
-                    "DEBUG: Authority IDs don't match! '{}' != '{}'",
-                    auth_id, authority_id
-                );
+            authority.clock_health().record_sync(sync.clone());
+        }
+        Ok(sync)
+    }
+
+    // send a timestamp request to the local authority or over TSP to a remote one
+    async fn send_timestamp_request(
+        &self,
+        authority_id: &str,
+        request: TimestampRequest,
+    ) -> Result<TrackedResponse, TimeServiceError> {
+        let nonce = request.nonce.clone();
+        self.client.track_request(&request, authority_id)?;
+
+        let response = match self.exchange(authority_id, request).await {
+            Ok(response) => response,
+            Err(e) => {
+                self.client.cancel_request(&nonce);
+                return Err(e);
+            }
+        };
+
+        // authorities don't sign rate-limit refusals, so they prove nothing and
+        // leave the request open to retry
+        if response.status == TimestampStatus::RateLimited
+            && response.timestamp.signature.is_empty()
+        {
+            self.client.cancel_request(&nonce);
+            return Err(TimeServiceError::RateLimitExceeded(
+                response.retry_after_secs,
+            ));
+        }
+
+        // a signed refusal answers the request too
+        let tracked = self.client.accept_response(&response)?;
+        match response.is_success() {
+            true => Ok(tracked),
+            false => Err(TimeServiceError::Refused(Box::new(response))),
+        }
+    }
+
+    // deliver a request to the local authority or over TSP to a remote one
+    async fn exchange(
+        &self,
+        authority_id: &str,
+        request: TimestampRequest,
+    ) -> Result<TimestampResponse, TimeServiceError> {
+        match &self.authority {
+            // serve requests for our local authority directly
+            Some(authority) if authority.get_id() == authority_id => {
+                authority.issue_timestamp(request).await
+            }
+            _ => {
+                self.remote_request(authority_id, METHOD_TIMESTAMP_REQUEST, &request)
+                    .await
+            }
+        }
+    }
+
+    // fetch a range of an authority's timestamp log, locally or over TSP
+    pub async fn get_log_range(
+        &self,
+        authority_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<AuthenticTimestamp>, TimeServiceError> {
+        let range = LogRange { from, to };
+        match &self.authority {
+            Some(authority) if authority.get_id() == authority_id => authority.log_range(range),
+            _ => {
+                self.remote_request(authority_id, METHOD_LOG_RANGE, &range)
+                    .await
             }
-        } else {
-            println!("DEBUG: No local authority configured");
         }
+    }
 
-        println!("DEBUG: Authority not found: {}", authority_id);
-        Err(TimeServiceError::AuthorityNotFound(
-            authority_id.to_string(),
-        ))
+    // fetch an authority's signed tree head, locally or over TSP
+    pub async fn get_tree_head(
+        &self,
+        authority_id: &str,
+    ) -> Result<SignedTreeHead, TimeServiceError> {
+        match &self.authority {
+            Some(authority) if authority.get_id() == authority_id => authority.signed_tree_head(),
+            _ => {
+                self.remote_request(authority_id, METHOD_TREE_HEAD, &())
+                    .await
+            }
+        }
+    }
+
+    // fetch a proof that a timestamp is in its authority's log at the given tree size;
+    // entries that are no longer recent need the leaf index from an earlier proof
+    pub async fn get_inclusion_proof(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        leaf_index: Option<u64>,
+        tree_size: u64,
+    ) -> Result<InclusionProof, TimeServiceError> {
+        let request = InclusionRequest {
+            leaf_hash: timestamp.log_leaf_hash()?,
+            leaf_index,
+            tree_size,
+        };
+        match &self.authority {
+            Some(authority) if authority.get_id() == timestamp.authority_id => {
+                authority.inclusion_proof(&request)
+            }
+            _ => {
+                self.remote_request(&timestamp.authority_id, METHOD_INCLUSION_PROOF, &request)
+                    .await
+            }
+        }
+    }
+
+    // fetch a proof that an authority's tree of `second` entries extends its tree of `first`
+    pub async fn get_consistency_proof(
+        &self,
+        authority_id: &str,
+        first: u64,
+        second: u64,
+    ) -> Result<ConsistencyProof, TimeServiceError> {
+        let request = ConsistencyRequest { first, second };
+        match &self.authority {
+            Some(authority) if authority.get_id() == authority_id => {
+                authority.consistency_proof(request)
+            }
+            _ => {
+                self.remote_request(authority_id, METHOD_CONSISTENCY_PROOF, &request)
+                    .await
+            }
+        }
+    }
+
+    // send a request over TSP to the endpoint registered for an authority
+    async fn remote_request<T, R>(
+        &self,
+        authority_id: &str,
+        method: &str,
+        request: &T,
+    ) -> Result<R, TimeServiceError>
+    where
+        T: Serialize + Sync,
+        R: DeserializeOwned,
+    {
+        let endpoint = self
+            .authority_endpoints
+            .get(authority_id)
+            .ok_or_else(|| TimeServiceError::AuthorityNotFound(authority_id.to_string()))?;
+        let transport = self
+            .tsp_client
+            .as_deref()
+            .ok_or_else(|| TimeServiceError::generic("No TSP transport configured"))?;
+
+        transport.send_request(endpoint, method, request).await
     }
 
     // verify a timestamp received from an authority
@@ -158,22 +740,141 @@ impl TspTimeService {
         self.client.verify_timestamp(timestamp)
     }
 
+    // verify that `earlier` precedes `later` in an authority's log, given the entries between them
+    pub fn verify_chain(
+        &self,
+        earlier: &AuthenticTimestamp,
+        later: &AuthenticTimestamp,
+        links: &[AuthenticTimestamp],
+    ) -> Result<bool, TimeServiceError> {
+        self.client.verify_chain(earlier, later, links)
+    }
+
+    // verify that a timestamp is in its authority's log as of a signed tree head
+    pub fn verify_inclusion(
+        &self,
+        timestamp: &AuthenticTimestamp,
+        proof: &InclusionProof,
+        head: &SignedTreeHead,
+    ) -> Result<bool, TimeServiceError> {
+        self.client.verify_inclusion(timestamp, proof, head)
+    }
+
+    // verify that a newer tree head extends an older one
+    pub fn verify_consistency(
+        &self,
+        older: &SignedTreeHead,
+        newer: &SignedTreeHead,
+        proof: &ConsistencyProof,
+    ) -> Result<bool, TimeServiceError> {
+        self.client.verify_consistency(older, newer, proof)
+    }
+
+    // set the freshness and trust rules used by evaluate_timestamp
+    pub fn set_verification_policy(&mut self, policy: VerificationPolicy) {
+        self.client.set_policy(policy);
+    }
+
+    // check a timestamp's signature and freshness against the verification policy
+    pub fn evaluate_timestamp(&self, timestamp: &AuthenticTimestamp) -> Verdict {
+        self.client.evaluate_timestamp(timestamp)
+    }
+
+    // verify a quorum bundle against the given threshold, allowing the
+    // authorities to have been asked up to `max_rtt` apart
+    pub fn verify_quorum_timestamp(
+        &self,
+        bundle: &QuorumTimestamp,
+        threshold: usize,
+        max_rtt: Duration,
+    ) -> Result<bool, TimeServiceError> {
+        self.client
+            .verify_quorum_timestamp(bundle, threshold, max_rtt)
+    }
+
     // process a timestamp request (when acting as an authority)
     pub async fn process_timestamp_request(
         &self,
-        request: crate::models::TimestampRequest,
-    ) -> Result<crate::models::TimestampResponse, TimeServiceError> {
+        request: TimestampRequest,
+    ) -> Result<TimestampResponse, TimeServiceError> {
+        self.process_timestamp_request_from(request, None).await
+    }
+
+    // process a timestamp request received from a network source (when acting as an authority)
+    pub async fn process_timestamp_request_from(
+        &self,
+        request: TimestampRequest,
+        source: Option<&str>,
+    ) -> Result<TimestampResponse, TimeServiceError> {
         if let Some(authority) = &self.authority {
-            authority.issue_timestamp(request).await
+            authority.issue_timestamp_from(request, source).await
         } else {
             Err(TimeServiceError::generic("Not configured as an authority"))
         }
     }
+
+    // handle a serialized request received over TSP (when acting as an authority)
+    pub async fn handle_envelope(&self, request: RequestEnvelope) -> ResponseEnvelope {
+        let result = match request.method.as_str() {
+            METHOD_TIMESTAMP_REQUEST => match request.decode::<TimestampRequest>() {
+                Ok(req) => self
+                    .process_timestamp_request_from(req, request.source.as_deref())
+                    .await
+                    .and_then(|response| ResponseEnvelope::ok(&response)),
+                Err(e) => Err(e),
+            },
+            METHOD_PUBLIC_KEY => match self.get_authority_public_key() {
+                Some(key) => ResponseEnvelope::ok(&key),
+                None => Err(TimeServiceError::generic("Not configured as an authority")),
+            },
+            METHOD_METADATA => match self.get_authority_metadata() {
+                Some(metadata) => ResponseEnvelope::ok(&metadata),
+                None => Err(TimeServiceError::generic("Not configured as an authority")),
+            },
+            METHOD_LOG_RANGE => match (&self.authority, request.decode::<LogRange>()) {
+                (Some(authority), Ok(range)) => authority
+                    .log_range(range)
+                    .and_then(|entries| ResponseEnvelope::ok(&entries)),
+                (None, _) => Err(TimeServiceError::generic("Not configured as an authority")),
+                (_, Err(e)) => Err(e),
+            },
+            METHOD_TREE_HEAD => match &self.authority {
+                Some(authority) => authority
+                    .signed_tree_head()
+                    .and_then(|head| ResponseEnvelope::ok(&head)),
+                None => Err(TimeServiceError::generic("Not configured as an authority")),
+            },
+            METHOD_INCLUSION_PROOF => match (&self.authority, request.decode::<InclusionRequest>())
+            {
+                (Some(authority), Ok(req)) => authority
+                    .inclusion_proof(&req)
+                    .and_then(|proof| ResponseEnvelope::ok(&proof)),
+                (None, _) => Err(TimeServiceError::generic("Not configured as an authority")),
+                (_, Err(e)) => Err(e),
+            },
+            METHOD_CONSISTENCY_PROOF => {
+                match (&self.authority, request.decode::<ConsistencyRequest>()) {
+                    (Some(authority), Ok(req)) => authority
+                        .consistency_proof(req)
+                        .and_then(|proof| ResponseEnvelope::ok(&proof)),
+                    (None, _) => Err(TimeServiceError::generic("Not configured as an authority")),
+                    (_, Err(e)) => Err(e),
+                }
+            }
+            other => Err(TimeServiceError::generic(format!(
+                "Unknown method: {}",
+                other
+            ))),
+        };
+
+        result.unwrap_or_else(ResponseEnvelope::error)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::TimestampStatus;
 
     #[tokio::test]
     async fn test_local_request_and_verify() {
@@ -193,4 +894,184 @@ mod tests {
         let is_valid = service.verify_timestamp(&timestamp).unwrap();
         assert!(is_valid);
     }
+
+    #[tokio::test]
+    async fn test_new_key_is_valid_from_the_configured_clock() {
+        let then = chrono::Utc::now() - chrono::Duration::days(365);
+        let mut service = TspTimeService::new();
+        service.set_clock(Arc::new(crate::clock::ManualClock::new(then)));
+        service.as_authority("test.authority".to_string());
+
+        let metadata = service.get_authority_metadata().unwrap();
+        assert_eq!(metadata.keys[0].valid_from, then);
+
+        // timestamps from the configured clock fall within the key's window
+        let timestamp = service.request_timestamp("test.authority").await.unwrap();
+        assert_eq!(timestamp.timestamp, then);
+        assert!(service.verify_timestamp(&timestamp).unwrap());
+    }
+
+    #[test]
+    fn test_authority_key_survives_restart() {
+        let path = std::env::temp_dir().join(format!("ats-key-{:x}.jwk", rand::random::<u64>()));
+        let config =
+            AuthorityConfig::new("persistent.authority".to_string()).with_key_file(KeyFileConfig {
+                path: path.clone(),
+                format: KeyFormat::Jwk,
+                passphrase: None,
+                create_if_missing: true,
+            });
+
+        let mut first = TspTimeService::new();
+        first.as_authority_with_config(config.clone()).unwrap();
+        let mut restarted = TspTimeService::new();
+        restarted.as_authority_with_config(config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            first.get_authority_public_key(),
+            restarted.get_authority_public_key()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authority_signs_only_once_synchronized() {
+        let network = crate::loopback::LoopbackNetwork::default();
+        let peers: Vec<String> = ["peer.a", "peer.b", "peer.c"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        for id in &peers {
+            network.spawn_authority(id);
+        }
+
+        let mut service = network.spawn_client(None).unwrap();
+        service
+            .as_authority_with_config(
+                AuthorityConfig::new("local.authority".to_string())
+                    .with_max_clock_error(Duration::from_secs(1)),
+            )
+            .unwrap();
+        let key = service.get_authority_public_key().unwrap();
+        service
+            .add_authority_key("local.authority".to_string(), &key)
+            .unwrap();
+
+        // An unsynchronized clock gets a signed refusal with a retry hint
+        match service.request_timestamp("local.authority").await {
+            Err(TimeServiceError::Refused(response)) => {
+                assert_eq!(response.status, TimestampStatus::ClockUnsynchronized);
+                assert!(response.retry_after().is_some());
+            }
+            other => panic!("expected a refusal, got {:?}", other),
+        }
+
+        // Peers on the same host agree with our clock to well within a second
+        let sync = service.synchronize_clock(&peers, 2).await.unwrap();
+        assert!(sync.error_bound() < Duration::from_secs(1));
+
+        let timestamp = service.request_timestamp("local.authority").await.unwrap();
+        assert!(timestamp.accuracy.is_some());
+        assert!(service.verify_timestamp(&timestamp).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_transparency_log_proofs_over_the_network() {
+        let network = crate::loopback::LoopbackNetwork::default();
+        network.spawn_authority("logged.authority");
+        let client = network.spawn_client(None).unwrap();
+
+        let first = client.request_timestamp("logged.authority").await.unwrap();
+        client.request_timestamp("logged.authority").await.unwrap();
+        let older = client.get_tree_head("logged.authority").await.unwrap();
+        for _ in 0..3 {
+            client.request_timestamp("logged.authority").await.unwrap();
+        }
+        let newer = client.get_tree_head("logged.authority").await.unwrap();
+        assert_eq!((older.tree_size, newer.tree_size), (2, 5));
+
+        // the first timestamp is still in the grown log, found by hash or by position
+        let proof = client
+            .get_inclusion_proof(&first, None, newer.tree_size)
+            .await
+            .unwrap();
+        assert!(client.verify_inclusion(&first, &proof, &newer).unwrap());
+        let by_index = client
+            .get_inclusion_proof(&first, Some(proof.leaf_index), newer.tree_size)
+            .await
+            .unwrap();
+        assert_eq!(by_index, proof);
+
+        // and the grown log only appended to what the older head covered
+        let consistency = client
+            .get_consistency_proof("logged.authority", older.tree_size, newer.tree_size)
+            .await
+            .unwrap();
+        assert!(client
+            .verify_consistency(&older, &newer, &consistency)
+            .unwrap());
+
+        // a head whose root was swapped no longer verifies
+        let mut forged = newer.clone();
+        forged.root_hash = older.root_hash.clone();
+        assert!(!client
+            .verify_consistency(&older, &forged, &consistency)
+            .unwrap());
+    }
+
+    /// Transport that delivers envelopes straight to another service
+    struct DirectTransport {
+        remote: std::sync::Arc<TspTimeService>,
+    }
+
+    #[async_trait]
+    impl TspCommunication for DirectTransport {
+        async fn send_envelope(
+            &self,
+            endpoint: &str,
+            request: RequestEnvelope,
+        ) -> Result<ResponseEnvelope, TimeServiceError> {
+            assert_eq!(endpoint, "tsp://remote");
+            Ok(self.remote.handle_envelope(request).await)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_request_over_transport() {
+        let mut remote = TspTimeService::new();
+        remote.as_authority("remote.authority".to_string());
+        let remote_key = remote.get_authority_public_key().unwrap();
+        let remote = std::sync::Arc::new(remote);
+
+        let mut service = TspTimeService::new();
+        service.set_transport(Box::new(DirectTransport {
+            remote: remote.clone(),
+        }));
+
+        // Without an endpoint the authority cannot be reached
+        assert!(matches!(
+            service.request_timestamp("remote.authority").await,
+            Err(TimeServiceError::AuthorityNotFound(_))
+        ));
+
+        service.add_authority_endpoint("remote.authority".to_string(), "tsp://remote".to_string());
+        service
+            .add_authority_key("remote.authority".to_string(), &remote_key)
+            .unwrap();
+
+        let timestamp = service
+            .request_data_timestamp("remote.authority", b"remote document")
+            .await
+            .unwrap();
+        assert!(timestamp.covers_data(b"remote document"));
+        assert!(service.verify_timestamp(&timestamp).unwrap());
+
+        // The public key is also reachable over the transport
+        let transport: &dyn TspCommunication = service.tsp_client.as_deref().unwrap();
+        let fetched: Vec<u8> = transport
+            .send_request("tsp://remote", METHOD_PUBLIC_KEY, &())
+            .await
+            .unwrap();
+        assert_eq!(fetched, remote_key);
+    }
 }